@@ -1,23 +1,293 @@
 #[macro_use]
 extern crate seed;
 use seed::prelude::*;
+use std::cell::RefCell;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::JsCast;
 
 mod sudoku;
-use sudoku::{Board, Cell};
+use sudoku::{
+    value_to_char, Board, BudgetExceeded, Cell, Difficulty, Rng, SolverState, Step, StepResult,
+    Variant,
+};
+
+// The number of constants in a newly generated 9x9 puzzle.
+const NEW_PUZZLE_CLUES: usize = 30;
+
+// The board sizes that can be picked in the UI.
+const BOARD_SIZES: [usize; 5] = [4, 6, 9, 12, 16];
+
+// An empty board of one of the `BOARD_SIZES`. The sizes that aren't perfect
+// squares get the usual rectangular boxes, 3 by 2 for 6x6 and 4 by 3 for
+// 12x12.
+fn empty_board(n: usize) -> Result<Board, String> {
+    match n {
+        6 => Ok(Board::with_boxes(6, 3, 2)),
+        12 => Ok(Board::with_boxes(12, 4, 3)),
+        _ => Board::try_new(n),
+    }
+}
+
+// Larger boards take far longer to generate with as few clues, so they keep
+// half of their cells.
+fn new_puzzle_clues(n: usize) -> usize {
+    if n <= 9 {
+        NEW_PUZZLE_CLUES * n * n / 81
+    } else {
+        n * n / 2
+    }
+}
+
+// The localStorage key the board is saved under between page loads.
+const STORAGE_KEY: &str = "sudoku-www-board";
+// And the one the theme is saved under.
+const THEME_KEY: &str = "sudoku-www-theme";
+
+// Boards up to this size are solved quicker than a web worker starts up.
+const MAX_MAIN_THREAD_SIZE: usize = 9;
+// The search on the page itself gives up after this many nodes rather than
+// freeze it, which leaves the hardest 9x9 puzzles plenty of room.
+const MAX_MAIN_THREAD_NODES: usize = 100_000;
+
+// A puzzle solved without help scores `MAX_SCORE`, and every hint and
+// mistake costs some of it.
+const MAX_SCORE: u32 = 1000;
+const HINT_PENALTY: u32 = 50;
+const MISTAKE_PENALTY: u32 = 20;
+
+// The number of colors cells can be painted with, see `.color-1` and on in
+// the styles.
+const COLORS: u8 = 6;
+
+// The milliseconds between two steps of a solve that is being watched.
+const ANIMATION_INTERVAL: i32 = 50;
+
+// Hands a message to the app, see `MAILBOX`.
+type Sender = Box<dyn Fn(Msg)>;
+
+thread_local! {
+    // Lets code outside of seed's event handling, like the web workers, send
+    // messages to the app. Set up by `render`.
+    static MAILBOX: RefCell<Option<Sender>> = RefCell::new(None);
+}
+
+fn send(msg: Msg) {
+    MAILBOX.with(|mailbox| {
+        if let Some(send) = mailbox.borrow().as_ref() {
+            send(msg);
+        }
+    });
+}
 
 // Model
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    // Entering a puzzle: everything can be edited, and values become givens.
+    Setup,
+    // Solving it: the givens are locked.
+    Play,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Theme {
+    Light,
+    Dark,
+}
+
+// A solve running in web workers, one for every branch of the search.
+struct Solving {
+    pub id: u32,
+    pub board: Board,
+    pub workers: Vec<web_sys::Worker>,
+    // The number of workers that haven't answered yet.
+    pub pending: usize,
+    // Shared by the workers, and kept alive for as long as they might answer.
+    pub _on_message: Closure<dyn FnMut(web_sys::MessageEvent)>,
+}
+
+// A solve shown one step at a time, driven by a timer.
+struct Animation {
+    pub board: Board,
+    pub state: SolverState,
+    pub interval: i32,
+    // Kept alive for as long as the timer calls it.
+    pub _on_tick: Closure<dyn FnMut()>,
+}
+
 struct Model {
     pub board: Board,
     pub warning: String,
-    pub selected: Option<(usize, usize)>,
+    // The selected cells, in the order they were selected. Edits go to all
+    // of them, while the last one is where the arrow keys move from.
+    pub selected: Vec<(usize, usize)>,
+    // Whether the mouse is held down on the board, adding the cells it passes
+    // over to the selection.
+    pub dragging: bool,
+    pub conflicts: Vec<(usize, usize)>,
+    pub undo_stack: Vec<Board>,
+    pub redo_stack: Vec<Board>,
+    pub rng: Rng,
+    pub import: String,
+    pub elapsed_seconds: u32,
+    // The clock only starts with the first edit of a fresh puzzle.
+    pub timer_running: bool,
+    // The givens the solution was worked out for, and their solution if
+    // there is exactly one. See `solution`.
+    pub solution: Option<(Board, Option<Board>)>,
+    // The entries that disagreed with the solution, and where the last one
+    // was made so it can be flashed.
+    pub mistakes: u32,
+    pub mistake: Option<(usize, usize)>,
+    // Every wrong entry counted among the mistakes, by position and value, so
+    // that checking the board again doesn't count it twice.
+    pub counted_mistakes: Vec<(usize, usize, u8)>,
+    // The hints given since the puzzle was started.
+    pub hints_used: u32,
+    // The cells the solver or a hint filled in, shown apart from the player's
+    // own entries until they are changed. See `reveal`.
+    pub revealed: Vec<(usize, usize)>,
+    // Whether typed values go into the pencil marks instead of the cell.
+    pub note_mode: bool,
+    // Whether entering a value removes it from the pencil marks of its peers.
+    pub auto_prune: bool,
+    pub mode: Mode,
+    pub theme: Theme,
+    // The rating of the last generated or imported puzzle.
+    pub difficulty: Option<Difficulty>,
+    pub solving: Option<Solving>,
+    // Counts the solves handed to workers, so late answers can be told apart.
+    pub solves: u32,
+    // The explanation asked for last, until the board changes.
+    pub steps: Vec<Step>,
+    // The entries marked wrong by the last check, until the board changes.
+    pub incorrect: Vec<(usize, usize)>,
+    // Whether the wrong entries are marked as they are made.
+    pub auto_check: bool,
+    pub animation: Option<Animation>,
 }
 
 impl Default for Model {
     fn default() -> Self {
-        Self {
-            board: Board::new(9),
-            warning: String::new(),
-            selected: None,
+        let (board, warning) = load_board();
+        let mut model = Self {
+            board,
+            warning,
+            selected: Vec::new(),
+            dragging: false,
+            conflicts: Vec::new(),
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            rng: Rng::new((js_sys::Math::random() * u64::MAX as f64) as u64),
+            import: String::new(),
+            elapsed_seconds: 0,
+            timer_running: false,
+            solution: None,
+            mistakes: 0,
+            mistake: None,
+            counted_mistakes: Vec::new(),
+            hints_used: 0,
+            revealed: Vec::new(),
+            note_mode: false,
+            auto_prune: true,
+            mode: Mode::Setup,
+            theme: load_theme(),
+            difficulty: None,
+            solving: None,
+            solves: 0,
+            steps: Vec::new(),
+            incorrect: Vec::new(),
+            auto_check: false,
+            animation: None,
+        };
+        board_changed(&mut model);
+        model
+    }
+}
+
+// The browser's localStorage. There may be none, e.g. when cookies are
+// blocked, in which case the board just isn't remembered.
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+// Restores the board saved by `save_board`, or an empty board if there is
+// nothing usable in storage or in the URL hash. Also returns a warning when
+// the hash holds something that isn't a puzzle.
+fn load_board() -> (Board, String) {
+    // Boards used to be saved in the line format.
+    let saved = local_storage()
+        .and_then(|storage| storage.get_item(STORAGE_KEY).unwrap_or(None))
+        .and_then(|saved| {
+            Board::from_json(&saved)
+                .or_else(|_| Board::from_line(&saved))
+                .ok()
+        });
+    let hash = seed::window().location().hash().unwrap_or_default();
+    let (shared, warning) = match Board::from_url_hash(&hash) {
+        Ok(shared) => (shared, String::new()),
+        Err(e) => (None, format!("The puzzle in the link is broken: {}", e)),
+    };
+    let board = match (shared, saved) {
+        // A link to some other puzzle wins over the one we were busy with,
+        // but only storage knows which values the player entered.
+        (Some(shared), Some(ref saved))
+            if shared.to_line() != saved.clear_variables().to_line() =>
+        {
+            shared
+        }
+        (Some(shared), None) => shared,
+        (_, saved) => saved.unwrap_or_else(|| Board::new(9)),
+    };
+    (board, warning)
+}
+
+// Whether `Board::from_line` can read back the board's `to_line`, which only
+// describes classic boards with square boxes and no cages.
+fn fits_line_format(board: &Board) -> bool {
+    board.variant == Variant::Classic && board.box_w == board.box_h && board.cages.is_empty()
+}
+
+fn load_theme() -> Theme {
+    let saved = local_storage().and_then(|storage| storage.get_item(THEME_KEY).unwrap_or(None));
+    match saved.as_deref() {
+        Some("dark") => Theme::Dark,
+        _ => Theme::Light,
+    }
+}
+
+fn save_theme(theme: Theme) {
+    if let Some(storage) = local_storage() {
+        let theme = match theme {
+            Theme::Light => "light",
+            Theme::Dark => "dark",
+        };
+        let _ = storage.set_item(THEME_KEY, theme);
+    }
+}
+
+fn save_board(board: &Board) {
+    if let Some(storage) = local_storage() {
+        // Not being able to save is no reason to stop the game. JSON keeps
+        // the boxes and tells the givens from the player's entries.
+        let _ = storage.set_item(STORAGE_KEY, &board.to_json());
+    }
+    // The hash makes the puzzle bookmarkable and shareable. It only holds
+    // the givens, as the line format reads every value back as one. Replacing
+    // the history entry keeps every edit from adding one.
+    let hash = if fits_line_format(board) {
+        format!("#{}", board.clear_variables().to_line())
+    } else {
+        String::new()
+    };
+    let window = seed::window();
+    if window.location().hash().ok().as_ref() != Some(&hash) {
+        if let Ok(history) = window.history() {
+            let url = if hash.is_empty() {
+                window.location().pathname().unwrap_or_default()
+            } else {
+                hash
+            };
+            let _ = history.replace_state_with_url(&JsValue::NULL, "", Some(&url));
         }
     }
 }
@@ -27,61 +297,701 @@ impl Default for Model {
 enum Msg {
     Solve,
     Clear,
-    Select(usize, usize),
-    CellUpdate(String),
+    Reset,
+    New,
+    Hint,
+    Check,
+    ToggleAutoCheck,
+    Explain,
+    Erase,
+    Rotate,
+    DragStart(usize, usize),
+    DragOver(usize, usize),
+    DragEnd,
+    Enter(u8),
     KeyDown(web_sys::KeyboardEvent),
+    Undo,
+    Redo,
+    ImportInput(String),
+    Import,
+    ToggleNote(u8),
+    ToggleNoteMode,
+    SetColor(u8),
+    AutoPencil,
+    ToggleAutoPrune,
+    Tick,
+    SetVariant(Variant),
+    SetSize(usize),
+    SetMode(Mode),
+    ToggleTheme,
+    WorkerDone(u32, Option<String>),
+    Watch,
+    AnimationStep,
 }
 
+// The cell selected last, see `Model::selected`.
+fn cursor(model: &Model) -> Option<(usize, usize)> {
+    model.selected.last().cloned()
+}
+
+// Puts the value in every selected cell. When several are selected, values
+// only go into the empty ones, so that a drag across a row doesn't overwrite
+// what is already there.
 fn update_cell(model: &Model, value: Option<u8>) -> Board {
-    if let Some((x, y)) = model.selected {
-        match value {
-            Some(v) => model.board.set(x, y, Cell::Constant(v)),
-            None => model.board.set(x, y, Cell::Empty),
+    let several = model.selected.len() > 1;
+    let mut board = model.board.clone();
+    for &(x, y) in model.selected.iter() {
+        if several && value.is_some() && board.get(x, y) != Cell::Empty {
+            continue;
+        }
+        // Only while setting up can the givens themselves be changed.
+        board = match (model.mode, value) {
+            (Mode::Setup, Some(v)) => board.set(x, y, Cell::Constant(v)),
+            (Mode::Setup, None) => board.set(x, y, Cell::Empty),
+            (Mode::Play, value) => board.enter(x, y, value),
+        };
+    }
+    board
+}
+
+// Replaces the board, remembering the old one so the change can be undone.
+fn push_board(model: &mut Model, board: Board) {
+    if board != model.board {
+        let previous = &model.board;
+        model
+            .revealed
+            .retain(|&(x, y)| board.get(x, y) == previous.get(x, y));
+        let previous = std::mem::replace(&mut model.board, board);
+        model.undo_stack.push(previous);
+        model.redo_stack.clear();
+        model.steps.clear();
+        model.incorrect.clear();
+        board_changed(model);
+    }
+}
+
+// Like `push_board`, for the player's own entries and notes. The first one
+// made while playing starts the clock.
+fn edit_board(model: &mut Model, board: Board) {
+    if model.mode == Mode::Play && board != model.board {
+        model.timer_running = true;
+    }
+    push_board(model, board);
+}
+
+// Works out again what is marked on the board, and saves it. Only needed when
+// the board changes, not after every message.
+fn board_changed(model: &mut Model) {
+    // Duplicates are marked as soon as they are entered.
+    model.conflicts = model.board.conflicts();
+    if model.auto_check {
+        model.incorrect = wrong_entries(model);
+        count_mistakes(model, model.incorrect.clone());
+    }
+    save_board(&model.board);
+}
+
+// Counts the entries on the board at these positions as mistakes, unless they
+// have been counted already.
+fn count_mistakes(model: &mut Model, wrong: Vec<(usize, usize)>) {
+    for (x, y) in wrong {
+        if let Some(v) = model.board.get(x, y).value() {
+            if !model.counted_mistakes.contains(&(x, y, v)) {
+                model.counted_mistakes.push((x, y, v));
+                model.mistakes += 1;
+            }
+        }
+    }
+}
+
+// Starts the game over: the clock is set back to zero, to start again with
+// the next edit, and the mistakes and hints are forgotten.
+fn restart(model: &mut Model) {
+    model.elapsed_seconds = 0;
+    model.timer_running = false;
+    model.mistakes = 0;
+    model.mistake = None;
+    model.counted_mistakes.clear();
+    model.hints_used = 0;
+    model.revealed.clear();
+}
+
+// Like `push_board`, for a board the solver or a hint filled in. The cells it
+// changed are shown as revealed rather than as the player's.
+fn reveal(model: &mut Model, board: Board) {
+    let changed: Vec<(usize, usize)> = board
+        .iter_cells()
+        .filter(|&(x, y, cell)| cell != model.board.get(x, y))
+        .map(|(x, y, _)| (x, y))
+        .collect();
+    push_board(model, board);
+    model.revealed.extend(changed);
+}
+
+fn score(model: &Model) -> u32 {
+    let penalty = model.hints_used * HINT_PENALTY + model.mistakes * MISTAKE_PENALTY;
+    MAX_SCORE.saturating_sub(penalty)
+}
+
+// The unique solution of the board's givens, worked out the first time it is
+// needed after they change. None if they don't have exactly one, or if that
+// takes too long to tell.
+fn solution(model: &mut Model) -> Option<Board> {
+    let mut givens = model.board.clear_variables();
+    // Painting the cells doesn't change the solution, so it is kept.
+    for color in givens.colors.iter_mut() {
+        *color = None;
+    }
+    match &model.solution {
+        Some((cached, solution)) if *cached == givens => solution.clone(),
+        _ => {
+            let solution = givens
+                .unique_solution_bounded(MAX_MAIN_THREAD_NODES)
+                .unwrap_or(None);
+            model.solution = Some((givens, solution.clone()));
+            solution
+        }
+    }
+}
+
+// The player's entries that disagree with the solution. Nothing is known to
+// be wrong without a unique solution.
+fn wrong_entries(model: &mut Model) -> Vec<(usize, usize)> {
+    let solution = match solution(model) {
+        Some(solution) => solution,
+        None => return Vec::new(),
+    };
+    model
+        .board
+        .iter_cells()
+        .filter(|&(x, y, cell)| match cell {
+            Cell::Variable(v) => solution.get(x, y).value() != Some(v),
+            _ => false,
+        })
+        .map(|(x, y, _)| (x, y))
+        .collect()
+}
+
+// Puts the value in the selected cells, counting a mistake for every cell
+// where it disagrees with the solution.
+fn enter_value(model: &mut Model, v: u8) {
+    if !model.board.is_valid_value(v) {
+        return;
+    }
+    let mut board = update_cell(model, Some(v));
+    if model.auto_prune {
+        for &(x, y) in model.selected.iter() {
+            if board.get(x, y) != model.board.get(x, y) {
+                board = board.prune_notes(x, y);
+            }
         }
+    }
+    let mut wrong = Vec::new();
+    if model.mode == Mode::Play && board != model.board {
+        let solution = solution(model);
+        wrong = model
+            .selected
+            .iter()
+            .cloned()
+            .filter(|&(x, y)| board.get(x, y) != model.board.get(x, y))
+            .filter(|&(x, y)| {
+                solution
+                    .as_ref()
+                    .is_some_and(|s| s.get(x, y).value() != Some(v))
+            })
+            .collect();
+        model.mistake = wrong.last().cloned();
+    }
+    edit_board(model, board);
+    count_mistakes(model, wrong);
+    announce_completion(model);
+}
+
+// A digit typed or picked on the number pad, which goes into the pencil marks
+// in note mode.
+fn type_value(model: &mut Model, v: u8) {
+    if model.note_mode {
+        toggle_note(model, v);
+    } else {
+        enter_value(model, v);
+    }
+}
+
+fn erase(model: &mut Model) {
+    let board = update_cell(model, None);
+    edit_board(model, board);
+}
+
+// Paints the selected cells. Picking the color they all have already takes
+// it away again.
+fn set_color(model: &mut Model, color: u8) {
+    let board = &model.board;
+    let painted = model
+        .selected
+        .iter()
+        .all(|&(x, y)| board.get_color(x, y) == Some(color));
+    let color = if painted { None } else { Some(color) };
+    let mut board = model.board.clone();
+    for &(x, y) in model.selected.iter() {
+        board = board.set_color(x, y, color);
+    }
+    push_board(model, board);
+}
+
+fn undo(model: &mut Model) {
+    if let Some(board) = model.undo_stack.pop() {
+        let next = std::mem::replace(&mut model.board, board);
+        model.redo_stack.push(next);
+        model.steps.clear();
+        model.incorrect.clear();
+        board_changed(model);
+    }
+}
+
+fn redo(model: &mut Model) {
+    if let Some(board) = model.redo_stack.pop() {
+        let previous = std::mem::replace(&mut model.board, board);
+        model.undo_stack.push(previous);
+        model.steps.clear();
+        model.incorrect.clear();
+        board_changed(model);
+    }
+}
+
+fn toggle_note(model: &mut Model, v: u8) {
+    if model.board.is_valid_value(v) {
+        let mut board = model.board.clone();
+        for &(x, y) in model.selected.iter() {
+            board = board.toggle_note(x, y, v);
+        }
+        edit_board(model, board);
+    }
+}
+
+// Lets the player know when they have filled in the last cell but got
+// something wrong. A correct board gets the banner from `solved_banner`.
+fn announce_completion(model: &mut Model) {
+    if model.board.empty_count() == 0 && !model.board.is_solved() {
+        model.warning = "Every cell is filled in, but something is wrong.".to_string();
+    } else {
+        model.warning = String::new();
+    }
+}
+
+fn stop_workers(model: &mut Model) {
+    if let Some(solving) = model.solving.take() {
+        for worker in solving.workers {
+            worker.terminate();
+        }
+    }
+}
+
+// Hands every branch of the search to its own web worker (see worker.js), so
+// that large boards don't freeze the page. The workers answer with
+// `Msg::WorkerDone`. Returns false if the board can't be solved this way.
+fn solve_in_workers(model: &mut Model) -> bool {
+    stop_workers(model);
+    // Small boards are solved right away, and the workers are sent the board
+    // in the line format.
+    let board = &model.board;
+    if board.n <= MAX_MAIN_THREAD_SIZE || !fits_line_format(board) {
+        return false;
+    }
+    let branches = board.branches();
+    if branches.len() < 2 {
+        return false;
+    }
+
+    model.solves += 1;
+    let id = model.solves;
+    let on_message = Closure::wrap(Box::new(move |event: web_sys::MessageEvent| {
+        send(Msg::WorkerDone(id, event.data().as_string()));
+    }) as Box<dyn FnMut(web_sys::MessageEvent)>);
+    let mut workers = Vec::new();
+    for branch in branches {
+        let worker = match web_sys::Worker::new("/worker.js") {
+            Ok(worker) => worker,
+            Err(_) => {
+                workers.iter().for_each(web_sys::Worker::terminate);
+                return false;
+            }
+        };
+        worker.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+        if worker
+            .post_message(&JsValue::from_str(&branch.to_line()))
+            .is_err()
+        {
+            worker.terminate();
+            workers.iter().for_each(web_sys::Worker::terminate);
+            return false;
+        }
+        workers.push(worker);
+    }
+    model.solving = Some(Solving {
+        id,
+        board: model.board.clone(),
+        pending: workers.len(),
+        workers,
+        _on_message: on_message,
+    });
+    true
+}
+
+// The first worker to find a solution wins. If none of them do, there is none.
+fn worker_done(model: &mut Model, id: u32, solution: Option<String>) {
+    let (board, pending) = match &mut model.solving {
+        Some(solving) if solving.id == id => {
+            solving.pending -= 1;
+            (solving.board.clone(), solving.pending)
+        }
+        // An answer to a solve that has been stopped.
+        _ => return,
+    };
+    let solution = solution.and_then(|line| Board::from_line(&line).ok());
+    if board != model.board {
+        // The board changed while the workers were busy.
+        stop_workers(model);
+        model.warning = String::new();
+    } else if let Some(solution) = solution {
+        stop_workers(model);
+        model.warning = String::new();
+        reveal(model, board.fill_from(&solution));
+    } else if pending == 0 {
+        stop_workers(model);
+        model.warning = "No solution exists for this puzzle.".to_string();
+    }
+}
+
+fn stop_animation(model: &mut Model) {
+    if let Some(animation) = model.animation.take() {
+        seed::window().clear_interval_with_handle(animation.interval);
+    }
+}
+
+// Starts stepping through the search on a timer, which sends
+// `Msg::AnimationStep`.
+fn start_animation(model: &mut Model) {
+    stop_animation(model);
+    let on_tick = Closure::wrap(Box::new(|| send(Msg::AnimationStep)) as Box<dyn FnMut()>);
+    let interval = seed::window().set_interval_with_callback_and_timeout_and_arguments_0(
+        on_tick.as_ref().unchecked_ref(),
+        ANIMATION_INTERVAL,
+    );
+    if let Ok(interval) = interval {
+        model.animation = Some(Animation {
+            board: model.board.clone(),
+            state: SolverState::new(&model.board),
+            interval,
+            _on_tick: on_tick,
+        });
+    }
+}
+
+fn animation_step(model: &mut Model) {
+    let animation = match &mut model.animation {
+        Some(animation) => animation,
+        None => return,
+    };
+    if animation.board != model.board {
+        // The board changed while we were watching.
+        stop_animation(model);
+        return;
+    }
+    match Board::solve_step(&mut animation.state) {
+        StepResult::Placed(..) | StepResult::Backtracked(..) => (),
+        StepResult::Done => {
+            let board = animation.board.fill_from(animation.state.board());
+            stop_animation(model);
+            reveal(model, board);
+        }
+        StepResult::Failed => {
+            stop_animation(model);
+            model.warning = "No solution exists for this puzzle.".to_string();
+        }
+    }
+}
+
+// Moves the selection one cell in the direction of the arrow key, stopping at
+// the edges of the board. Without a selection we start in the top left corner.
+fn move_selection(selected: Option<(usize, usize)>, n: usize, key: &str) -> Option<(usize, usize)> {
+    let (x, y) = match selected {
+        Some(s) => s,
+        None => return Some((0, 0)),
+    };
+    match key {
+        "ArrowUp" => Some((x, y.saturating_sub(1))),
+        "ArrowDown" => Some((x, (y + 1).min(n - 1))),
+        "ArrowLeft" => Some((x.saturating_sub(1), y)),
+        "ArrowRight" => Some(((x + 1).min(n - 1), y)),
+        _ => selected,
+    }
+}
+
+// The digit pressed together with Shift, if any. Shift changes what the digit
+// keys produce, so look at which key was pressed instead.
+fn shifted_digit(key_event: &web_sys::KeyboardEvent) -> Option<u8> {
+    if !key_event.shift_key() {
+        return None;
+    }
+    let code = key_event.code();
+    code.trim_start_matches("Digit")
+        .trim_start_matches("Numpad")
+        .parse()
+        .ok()
+}
+
+// Reads a pasted board, either as JSON (see `Board::to_json`) or in the line
+// format.
+fn parse_import(s: &str) -> Result<Board, String> {
+    let s = s.trim();
+    if s.starts_with('{') {
+        Board::from_json(s)
     } else {
-        model.board.clone()
+        Board::from_line(s)
     }
 }
 
+// Solves the board right here on the page, giving up after
+// `MAX_MAIN_THREAD_NODES` rather than freeze it. Without a solution, the
+// warning to show instead.
+fn solve_on_page(board: &Board) -> Result<Board, String> {
+    match board.solve_bounded(MAX_MAIN_THREAD_NODES) {
+        Ok(Some(solution)) => Ok(solution),
+        Ok(None) => Err("No solution exists for this puzzle.".to_string()),
+        Err(BudgetExceeded) => Err("Too hard to solve in time.".to_string()),
+    }
+}
+
+// Keys typed into the import field are meant for it, not for the board.
+fn typing_in_import_field(key_event: &web_sys::KeyboardEvent) -> bool {
+    key_event
+        .target()
+        .and_then(|target| target.dyn_into::<web_sys::Element>().ok())
+        .is_some_and(|element| element.class_name() == "import_field")
+}
+
 fn update(msg: Msg, model: &mut Model) -> Update<Msg> {
     match msg {
         Msg::Solve => {
             model.warning = String::new();
-            match model.board.solve() {
-                Some(board) => model.board = board,
-                None => model.warning = "This Sudoku is unsolvable!".to_string(),
-            };
-            model.selected = None;
+            let conflict = model.board.clear_variables().find_conflict();
+            if conflict.is_none() && model.board.is_valid() && solve_in_workers(model) {
+                model.warning = "Solving…".to_string();
+            } else if let Some((x, y)) = conflict {
+                model.warning = format!("Conflict at row {}, column {}!", y + 1, x + 1);
+            } else {
+                match solve_on_page(&model.board) {
+                    Err(warning) => model.warning = warning,
+                    // The solver only looks at the constants, so the player's own
+                    // entries may still be wrong.
+                    Ok(_) if !model.board.is_valid() => {
+                        model.warning = "Board has a conflict — check your entries".to_string();
+                    }
+                    Ok(board) => reveal(model, board),
+                }
+            }
+            model.selected.clear();
+        }
+        Msg::WorkerDone(id, solution) => worker_done(model, id, solution),
+        Msg::Watch => {
+            model.warning = String::new();
+            if model.animation.is_some() {
+                stop_animation(model);
+            } else if let Some((x, y)) = model.board.clear_variables().find_conflict() {
+                model.warning = format!("Conflict at row {}, column {}!", y + 1, x + 1);
+            } else {
+                start_animation(model);
+            }
         }
+        Msg::AnimationStep => animation_step(model),
         Msg::Clear => {
             model.warning = String::new();
-            model.board = Board::new(9);
-            model.selected = None;
+            let board = model.board.clear();
+            push_board(model, board);
+            model.difficulty = None;
+            model.selected.clear();
+            restart(model);
         }
-        Msg::Select(x, y) => {
-            if model.selected == Some((x, y)) {
-                model.selected = None;
-            } else {
-                model.selected = Some((x, y));
+        Msg::Reset => {
+            model.warning = String::new();
+            let board = model.board.clear_variables();
+            push_board(model, board);
+            model.selected.clear();
+            restart(model);
+        }
+        Msg::SetMode(mode) => model.mode = mode,
+        Msg::ToggleTheme => {
+            model.theme = match model.theme {
+                Theme::Light => Theme::Dark,
+                Theme::Dark => Theme::Light,
+            };
+            save_theme(model.theme);
+        }
+        Msg::SetSize(n) => match empty_board(n) {
+            Ok(mut board) => {
+                model.warning = String::new();
+                board.variant = model.board.variant;
+                push_board(model, board);
+                model.difficulty = None;
+                model.selected.clear();
+                restart(model);
             }
+            Err(e) => model.warning = e,
+        },
+        Msg::New => {
+            model.warning = String::new();
+            let (n, box_w, box_h) = (model.board.n, model.board.box_w, model.board.box_h);
+            let clues = new_puzzle_clues(n);
+            let board = Board::generate_bounded(
+                n,
+                box_w,
+                box_h,
+                clues,
+                MAX_MAIN_THREAD_NODES,
+                &mut model.rng,
+            );
+            model.difficulty = board.difficulty_bounded(MAX_MAIN_THREAD_NODES).ok();
+            push_board(model, board);
+            model.selected.clear();
+            // A generated puzzle is there to be played, with its givens locked.
+            model.mode = Mode::Play;
+            restart(model);
+            // Worked out up front, so the first entry doesn't have to wait.
+            solution(model);
         }
-        Msg::CellUpdate(s) => {
-            if let Ok(v) = s.parse::<u8>() {
-                if v > 0 && v as usize <= model.board.n {
-                    model.board = update_cell(model, Some(v));
+        Msg::Hint => {
+            model.warning = String::new();
+            if model.board.squares.contains(&Cell::Empty) {
+                match solve_on_page(&model.board) {
+                    Ok(solution) => {
+                        if let Some((x, y, v)) = model.board.hint_from(&solution, cursor(model)) {
+                            let board = model.board.set(x, y, Cell::Variable(v));
+                            reveal(model, board);
+                            model.hints_used += 1;
+                        }
+                    }
+                    Err(warning) => model.warning = warning,
                 }
-            } else if s == "" {
-                model.board = update_cell(model, None);
             }
         }
+        Msg::Check => {
+            let solution = solution(model);
+            model.incorrect = model.board.incorrect_cells_from(solution.as_ref());
+            count_mistakes(model, model.incorrect.clone());
+            model.warning = match model.incorrect.len() {
+                0 => "No mistakes so far.".to_string(),
+                1 => "1 entry is wrong.".to_string(),
+                wrong => format!("{} entries are wrong.", wrong),
+            };
+        }
+        Msg::ToggleAutoCheck => {
+            model.auto_check = !model.auto_check;
+            model.incorrect = if model.auto_check {
+                wrong_entries(model)
+            } else {
+                Vec::new()
+            };
+            count_mistakes(model, model.incorrect.clone());
+        }
+        Msg::Explain => {
+            model.warning = String::new();
+            match solve_on_page(&model.board) {
+                Ok(solution) => model.steps = model.board.steps_from(&solution),
+                Err(warning) => {
+                    model.steps.clear();
+                    model.warning = warning;
+                }
+            }
+        }
+        Msg::Erase => erase(model),
+        Msg::Rotate => {
+            let old = model.board.clone();
+            // The selection and whatever is known about the cells turn with
+            // the board.
+            let turn = |&(x, y): &(usize, usize)| old.rotate_cell_90(x, y);
+            let revealed = model.revealed.iter().map(turn).collect();
+            for mistake in model.counted_mistakes.iter_mut() {
+                let (x, y) = old.rotate_cell_90(mistake.0, mistake.1);
+                *mistake = (x, y, mistake.2);
+            }
+            model.mistake = model.mistake.as_ref().map(turn);
+            push_board(model, old.rotate_90());
+            model.revealed = revealed;
+            model.selected = model.selected.iter().map(turn).collect();
+        }
+        // A click is a drag that ends where it started, and clicking the only
+        // selected cell again deselects it.
+        Msg::DragStart(x, y) => {
+            if model.selected == [(x, y)] {
+                model.selected.clear();
+            } else {
+                model.selected = vec![(x, y)];
+                model.dragging = true;
+            }
+        }
+        Msg::DragOver(x, y) => {
+            if model.dragging && !model.selected.contains(&(x, y)) {
+                model.selected.push((x, y));
+            }
+        }
+        Msg::DragEnd => model.dragging = false,
+        Msg::Enter(v) => type_value(model, v),
+        Msg::KeyDown(ref key_event) if typing_in_import_field(key_event) => (),
         Msg::KeyDown(key_event) => {
             let key = key_event.key();
-            if let Ok(v) = key.parse::<u8>() {
-                if v > 0 && v as usize <= model.board.n {
-                    model.board = update_cell(model, Some(v));
+            // Cmd takes the place of Ctrl on macOS.
+            if key_event.ctrl_key() || key_event.meta_key() {
+                match key.as_str() {
+                    "z" | "Z" if key_event.shift_key() => redo(model),
+                    "z" | "Z" => undo(model),
+                    "y" | "Y" => redo(model),
+                    _ => (),
                 }
+            } else if let Some(v) = shifted_digit(&key_event) {
+                toggle_note(model, v);
+            } else if let Some(v) = model.board.parse_value(&key) {
+                type_value(model, v);
             } else if key == "Backspace" || key == "Delete" {
-                model.board = update_cell(model, None);
+                erase(model);
+            } else if key.starts_with("Arrow") {
+                key_event.prevent_default();
+                let moved = move_selection(cursor(model), model.board.n, &key);
+                model.selected = moved.into_iter().collect();
+            }
+        }
+        Msg::Undo => undo(model),
+        Msg::Redo => redo(model),
+        Msg::ToggleNote(v) => toggle_note(model, v),
+        Msg::ToggleNoteMode => model.note_mode = !model.note_mode,
+        Msg::SetColor(color) => set_color(model, color),
+        Msg::AutoPencil => {
+            let board = model.board.fill_notes();
+            edit_board(model, board);
+        }
+        Msg::ToggleAutoPrune => model.auto_prune = !model.auto_prune,
+        Msg::SetVariant(variant) => {
+            let mut board = model.board.clone();
+            board.variant = variant;
+            push_board(model, board);
+        }
+        Msg::ImportInput(s) => model.import = s,
+        Msg::Import => match parse_import(&model.import) {
+            Ok(board) => {
+                model.warning = String::new();
+                model.difficulty = board.difficulty_bounded(MAX_MAIN_THREAD_NODES).ok();
+                push_board(model, board);
+                model.selected.clear();
+                model.import = String::new();
+                model.mode = Mode::Play;
+                restart(model);
+                solution(model);
+            }
+            Err(e) => model.warning = e,
+        },
+        Msg::Tick => {
+            // The clock stops once the puzzle is done.
+            if model.timer_running && !model.board.is_solved() {
+                model.elapsed_seconds += 1;
             }
         }
     }
@@ -89,106 +999,502 @@ fn update(msg: Msg, model: &mut Model) -> Update<Msg> {
 }
 
 // View
-fn row(cells: &[Cell], y: usize, selected: Option<usize>) -> El<Msg> {
-    let cells = cells
+fn timer(elapsed_seconds: u32) -> El<Msg> {
+    p![
+        class!["timer"],
+        format!(
+            "{:02}:{:02}:{:02}",
+            elapsed_seconds / 3600,
+            elapsed_seconds / 60 % 60,
+            elapsed_seconds % 60
+        )
+    ]
+}
+
+// Buttons for entering values without a keyboard, e.g. on a phone. They
+// can't be used until a cell is selected, and digits that are on the board n
+// times are greyed out, as there is nowhere left to put them.
+fn number_pad(model: &Model) -> El<Msg> {
+    let mut attributes = attrs! {};
+    if model.selected.is_empty() {
+        attributes.add(At::Disabled, "disabled");
+    }
+    let counts = model.board.placed_counts();
+    let mut buttons = (1..=model.board.n as u8)
+        .map(|v| {
+            let text = value_to_char(v).to_string();
+            let mut attributes = attributes.clone();
+            if counts[v as usize] >= model.board.n {
+                attributes.add(At::Class, "complete");
+                attributes.add(At::Disabled, "disabled");
+            }
+            button![attributes, simple_ev(Ev::Click, Msg::Enter(v)), text]
+        })
+        .collect::<Vec<El<Msg>>>();
+    buttons.push(button![
+        attributes,
+        simple_ev(Ev::Click, Msg::Erase),
+        "Erase"
+    ]);
+    div![class!["number_pad"], buttons]
+}
+
+fn progress(sudoku: &Board) -> El<Msg> {
+    let left = sudoku.empty_count();
+    p![
+        class!["progress"],
+        format!("{} {} left", left, if left == 1 { "cell" } else { "cells" })
+    ]
+}
+
+fn plural(count: u32, one: &str, many: &str) -> String {
+    format!("{} {}", count, if count == 1 { one } else { many })
+}
+
+// The mistakes, hints and score of the current puzzle. Only kept while
+// playing a puzzle with a unique solution.
+fn stats(model: &Model) -> El<Msg> {
+    match &model.solution {
+        Some((_, Some(_))) if model.mode == Mode::Play => p![
+            class!["stats"],
+            format!(
+                "{}, {}, score {}",
+                plural(model.mistakes, "mistake", "mistakes"),
+                plural(model.hints_used, "hint", "hints"),
+                score(model)
+            )
+        ],
+        _ => seed::empty(),
+    }
+}
+
+// How much searching the solve being watched has done so far.
+fn animation_stats(animation: &Option<Animation>) -> El<Msg> {
+    match animation {
+        Some(animation) => {
+            let stats = animation.state.stats();
+            p![
+                class!["progress"],
+                format!("{} steps, {} backtracks", stats.nodes, stats.backtracks)
+            ]
+        }
+        None => seed::empty(),
+    }
+}
+
+fn solved_banner(sudoku: &Board) -> El<Msg> {
+    if sudoku.is_solved() {
+        p![class!["solved_banner"], "Well done, the puzzle is solved!"]
+    } else {
+        seed::empty()
+    }
+}
+
+fn steps(steps: &[Step]) -> El<Msg> {
+    if steps.is_empty() {
+        return seed::empty();
+    }
+    details![
+        class!["steps"],
+        summary![format!("Solving steps ({})", steps.len())],
+        ol![steps
+            .iter()
+            .map(|step| li![step.reason])
+            .collect::<Vec<El<Msg>>>()]
+    ]
+}
+
+fn difficulty(difficulty: Option<Difficulty>) -> El<Msg> {
+    let text = match difficulty {
+        Some(Difficulty::Easy) => "Difficulty: easy",
+        Some(Difficulty::Medium) => "Difficulty: medium",
+        Some(Difficulty::Hard) => "Difficulty: hard",
+        Some(Difficulty::Evil) => "Difficulty: evil",
+        None => return seed::empty(),
+    };
+    p![class!["difficulty"], text]
+}
+
+// The cells to mark on the board, besides the selection.
+struct Marks<'a> {
+    conflicts: &'a [(usize, usize)],
+    incorrect: &'a [(usize, usize)],
+    revealed: &'a [(usize, usize)],
+    mistake: Option<(usize, usize)>,
+}
+
+fn row(
+    sudoku: &Board,
+    y: usize,
+    selected: &[(usize, usize)],
+    selected_value: Option<u8>,
+    marks: &Marks,
+) -> El<Msg> {
+    let cells = sudoku.squares[y * sudoku.n..(y + 1) * sudoku.n]
         .iter()
         .enumerate()
         .map(|(x, cell)| {
             let mut classes = "cell".to_string();
-            if let Some(s) = selected {
-                if s == x {
-                    classes += " selected";
+            // The edges of the boxes are drawn thicker.
+            let (box_x, box_y) = (x % sudoku.box_w, y % sudoku.box_h);
+            if box_x == 0 {
+                classes += " box_left";
+            }
+            if box_x == sudoku.box_w - 1 {
+                classes += " box_right";
+            }
+            if box_y == 0 {
+                classes += " box_top";
+            }
+            if box_y == sudoku.box_h - 1 {
+                classes += " box_bottom";
+            }
+            // The peers are only shown for a single selected cell.
+            if selected.contains(&(x, y)) {
+                classes += " selected";
+            } else if let [s] = selected {
+                if sudoku.is_peer((x, y), *s) {
+                    classes += " peer";
                 }
-            };
-            if let Cell::Constant(_) = cell {
-                classes += " constant";
+            }
+            // Values entered in setup mode are constants, anything filled in
+            // while playing, by the solver or by hints is a variable. Only the
+            // player's own entries are told apart from the rest.
+            match cell {
+                Cell::Constant(_) => classes += " constant",
+                Cell::Variable(_) if marks.revealed.contains(&(x, y)) => classes += " solved",
+                Cell::Variable(_) => classes += " entry",
+                Cell::Empty => (),
+            }
+            // An empty selected cell has no value to match.
+            if selected_value.is_some()
+                && cell.value() == selected_value
+                && !selected.contains(&(x, y))
+            {
+                classes += " same-value";
+            }
+            if marks.conflicts.contains(&(x, y)) {
+                classes += " conflict";
+            }
+            if marks.incorrect.contains(&(x, y)) {
+                classes += " wrong";
+            }
+            if marks.mistake == Some((x, y)) {
+                classes += " mistake";
+            }
+            if let Some(color) = sudoku.get_color(x, y) {
+                classes += &format!(" color-{}", color);
+            }
+            if sudoku.variant == Variant::Diagonal && (x == y || x + y == sudoku.n - 1) {
+                classes += " diagonal";
             }
 
             let text = match cell {
-                Cell::Variable(v) | Cell::Constant(v) => format!("{}", v),
+                Cell::Variable(v) | Cell::Constant(v) => value_to_char(*v).to_string(),
                 Cell::Empty => String::new(),
             };
+            let notes = sudoku.get_notes(x, y);
+            let notes = if notes.is_empty() {
+                seed::empty()
+            } else {
+                let notes: Vec<String> = notes
+                    .iter()
+                    .map(|v| value_to_char(*v).to_string())
+                    .collect();
+                span![class!["notes"], notes.join(" ")]
+            };
 
             td![
-                simple_ev(Ev::Click, Msg::Select(x, y)),
+                simple_ev(Ev::MouseDown, Msg::DragStart(x, y)),
+                simple_ev(Ev::MouseEnter, Msg::DragOver(x, y)),
                 class![classes.as_str()],
-                text
+                text,
+                notes
             ]
         })
         .collect::<Vec<El<Msg>>>();
     tr![class!["row"], cells]
 }
 
-fn board(cells: &[Cell], n: usize, selected: Option<(usize, usize)>) -> El<Msg> {
-    let rows = cells
-        .chunks(n)
-        .enumerate()
-        .map(|(y, chunk)| {
-            let select = match selected {
-                Some(s) => {
-                    if s.1 == y {
-                        Some(s.0)
-                    } else {
-                        None
-                    }
-                }
-                None => None,
-            };
-            row(chunk, y, select)
-        })
+fn board(sudoku: &Board, selected: &[(usize, usize)], marks: &Marks) -> El<Msg> {
+    let selected_value = match selected {
+        [(x, y)] => sudoku.get(*x, *y).value(),
+        _ => None,
+    };
+    let rows = (0..sudoku.n)
+        .map(|y| row(sudoku, y, selected, selected_value, marks))
         .collect::<Vec<El<Msg>>>();
-    table![class!["board"], rows]
+    // Larger boards get smaller cells, see `.size_12` and `.size_16` in the styles.
+    let classes = format!("board size_{}", sudoku.n);
+    // Letting go of the mouse outside of the board also ends a drag.
+    table![
+        class![classes.as_str()],
+        simple_ev(Ev::MouseUp, Msg::DragEnd),
+        simple_ev(Ev::MouseLeave, Msg::DragEnd),
+        rows
+    ]
 }
 
 fn view(model: &Model) -> El<Msg> {
-    let squares = &model.board.squares;
-    let n = model.board.n;
-
-    let input_value = match model.selected {
-        Some((x, y)) => match model.board.get(x, y) {
-            Cell::Variable(v) | Cell::Constant(v) => format!("{}", v),
-            Cell::Empty => String::new(),
-        },
-        None => String::new(),
+    // While a solve is being watched, the board shows how far it has got.
+    let (shown, watch_text) = match &model.animation {
+        Some(animation) => (animation.state.board(), "Stop"),
+        None => (&model.board, "Watch"),
+    };
+    // Everything the search has filled in so far counts as revealed.
+    let revealed: Vec<(usize, usize)> = match &model.animation {
+        Some(_) => shown
+            .iter_cells()
+            .filter(|&(_, _, cell)| !cell.is_constant())
+            .map(|(x, y, _)| (x, y))
+            .collect(),
+        None => model.revealed.clone(),
+    };
+    let marks = Marks {
+        conflicts: &model.conflicts,
+        incorrect: &model.incorrect,
+        revealed: &revealed,
+        mistake: model.mistake,
     };
-    let input_field: El<Msg> = input![
-        class!["input_field"],
-        attrs! {
-            At::Value => input_value;
-            At::AutoFocus => true
-        },
-        input_ev(Ev::Input, Msg::CellUpdate)
-    ];
 
     div![
-        class!["container"],
+        class![match model.theme {
+            Theme::Light => "container",
+            // Restyles everything inside, see `.dark` in the styles.
+            Theme::Dark => "container dark",
+        }],
         div![
-            board(squares, n, model.selected),
+            timer(model.elapsed_seconds),
+            board(shown, &model.selected, &marks),
+            number_pad(model),
+            progress(&model.board),
+            stats(model),
+            animation_stats(&model.animation),
+            difficulty(model.difficulty),
             button![
                 class!["solve_button"],
                 simple_ev(Ev::Click, Msg::Solve),
                 format!("Solve")
             ],
+            button![
+                class!["hint_button"],
+                simple_ev(Ev::Click, Msg::Hint),
+                format!("Hint")
+            ],
+            button![
+                class!["check_button"],
+                simple_ev(Ev::Click, Msg::Check),
+                format!("Check")
+            ],
+            button![
+                class!["auto_check_button"],
+                simple_ev(Ev::Click, Msg::ToggleAutoCheck),
+                if model.auto_check {
+                    "Auto-check: on"
+                } else {
+                    "Auto-check: off"
+                }
+            ],
+            button![
+                class!["explain_button"],
+                simple_ev(Ev::Click, Msg::Explain),
+                format!("Explain")
+            ],
+            button![
+                class!["watch_button"],
+                simple_ev(Ev::Click, Msg::Watch),
+                watch_text
+            ],
+            button![
+                class!["erase_button"],
+                simple_ev(Ev::Click, Msg::Erase),
+                format!("Erase")
+            ],
+            button![
+                class!["rotate_button"],
+                simple_ev(Ev::Click, Msg::Rotate),
+                format!("Rotate")
+            ],
+            div![
+                class!["size_buttons"],
+                BOARD_SIZES
+                    .iter()
+                    .map(|&n| {
+                        let class = if n == model.board.n {
+                            "size_button current"
+                        } else {
+                            "size_button"
+                        };
+                        button![
+                            class![class],
+                            simple_ev(Ev::Click, Msg::SetSize(n)),
+                            format!("{}x{}", n, n)
+                        ]
+                    })
+                    .collect::<Vec<El<Msg>>>()
+            ],
+            button![
+                class!["mode_button"],
+                simple_ev(
+                    Ev::Click,
+                    Msg::SetMode(match model.mode {
+                        Mode::Setup => Mode::Play,
+                        Mode::Play => Mode::Setup,
+                    })
+                ),
+                match model.mode {
+                    Mode::Setup => "Mode: setup",
+                    Mode::Play => "Mode: play",
+                }
+            ],
+            button![
+                class!["variant_button"],
+                simple_ev(
+                    Ev::Click,
+                    Msg::SetVariant(match model.board.variant {
+                        Variant::Classic => Variant::Diagonal,
+                        Variant::Diagonal => Variant::AntiKnight,
+                        Variant::AntiKnight => Variant::Classic,
+                    })
+                ),
+                match model.board.variant {
+                    Variant::Classic => "Rules: classic",
+                    Variant::Diagonal => "Rules: diagonal",
+                    Variant::AntiKnight => "Rules: anti-knight",
+                }
+            ],
             button![
                 class!["clear_button"],
                 simple_ev(Ev::Click, Msg::Clear),
                 format!("Clear")
             ],
-            input_field,
+            button![
+                class!["reset_button"],
+                simple_ev(Ev::Click, Msg::Reset),
+                format!("Reset")
+            ],
+            button![
+                class!["new_button"],
+                simple_ev(Ev::Click, Msg::New),
+                format!("New")
+            ],
+            button![
+                class!["undo_button"],
+                simple_ev(Ev::Click, Msg::Undo),
+                format!("Undo")
+            ],
+            button![
+                class!["redo_button"],
+                simple_ev(Ev::Click, Msg::Redo),
+                format!("Redo")
+            ],
+            button![
+                class!["theme_button"],
+                simple_ev(Ev::Click, Msg::ToggleTheme),
+                match model.theme {
+                    Theme::Light => "Dark mode",
+                    Theme::Dark => "Light mode",
+                }
+            ],
+            div![
+                class!["color_buttons"],
+                (1..=COLORS)
+                    .map(|color| {
+                        let class = format!("color_button color-{}", color);
+                        button![
+                            class![class.as_str()],
+                            simple_ev(Ev::Click, Msg::SetColor(color)),
+                            color.to_string()
+                        ]
+                    })
+                    .collect::<Vec<El<Msg>>>(),
+                " Colors"
+            ],
+            div![
+                class!["note_buttons"],
+                (1..=model.board.n as u8)
+                    .map(|v| button![
+                        simple_ev(Ev::Click, Msg::ToggleNote(v)),
+                        value_to_char(v).to_string()
+                    ])
+                    .collect::<Vec<El<Msg>>>(),
+                " Pencil marks (or Shift + digit) ",
+                button![
+                    class!["note_mode_button"],
+                    simple_ev(Ev::Click, Msg::ToggleNoteMode),
+                    if model.note_mode {
+                        "Note mode: on"
+                    } else {
+                        "Note mode: off"
+                    }
+                ],
+                button![
+                    class!["auto_pencil_button"],
+                    simple_ev(Ev::Click, Msg::AutoPencil),
+                    "Auto-pencil"
+                ],
+                button![
+                    class!["auto_prune_button"],
+                    simple_ev(Ev::Click, Msg::ToggleAutoPrune),
+                    if model.auto_prune {
+                        "Prune notes: on"
+                    } else {
+                        "Prune notes: off"
+                    }
+                ]
+            ],
+            div![
+                textarea![
+                    class!["import_field"],
+                    attrs! {
+                        At::Value => model.import;
+                        At::Placeholder => "Paste a puzzle, e.g. 53..7.... or the JSON from another tool"
+                    },
+                    input_ev(Ev::Input, Msg::ImportInput)
+                ],
+                button![
+                    class!["import_button"],
+                    simple_ev(Ev::Click, Msg::Import),
+                    format!("Import")
+                ]
+            ],
+            solved_banner(&model.board),
+            steps(&model.steps),
             p![class!["warning_text"], model.warning],
             p![class!["author_text"], "© 2019 Yrjan Skrimstad"]
         ]
     ]
 }
 
+// All typing on the board arrives here, so nothing needs to have focus for
+// it to work.
 fn window_events(_: &Model) -> Vec<seed::dom_types::Listener<Msg>> {
     vec![keyboard_ev("keydown", Msg::KeyDown)]
 }
 
 #[wasm_bindgen]
 pub fn render() {
-    seed::App::build(Model::default(), update, view)
+    let app = seed::App::build(Model::default(), update, view)
         .window_events(window_events)
         .finish()
         .run();
+    let mailbox = app.clone();
+    MAILBOX.with(|m| *m.borrow_mut() = Some(Box::new(move |msg| mailbox.update(msg))));
+    seed::set_interval(Box::new(move || app.update(Msg::Tick)), 1000);
+}
+
+/// Solves a puzzle for other pages using the engine, see `sudoku::solve_puzzle`
+/// for the format. E.g. `wasm_bindgen.solve_puzzle("53..7....6..195...")`.
+#[wasm_bindgen]
+pub fn solve_puzzle(input: &str) -> Option<String> {
+    sudoku::solve_puzzle(input)
+}
+
+/// Solves a board given in the line format, for worker.js.
+#[wasm_bindgen]
+pub fn solve_line(line: &str) -> Option<String> {
+    Board::from_line(line)
+        .ok()?
+        .solve()
+        .map(|solution| solution.to_line())
 }
@@ -1,5 +1,7 @@
 use std::collections::HashSet;
 use std::fmt;
+use std::iter::Peekable;
+use std::str::{Chars, FromStr};
 
 #[derive(Clone, Copy, PartialEq)]
 pub enum Cell {
@@ -8,6 +10,20 @@ pub enum Cell {
     Empty,
 }
 
+impl Cell {
+    pub fn is_constant(self) -> bool {
+        matches!(self, Cell::Constant(_))
+    }
+
+    /// The value of a filled cell, whether it is a constant or not.
+    pub fn value(self) -> Option<u8> {
+        match self {
+            Cell::Variable(v) | Cell::Constant(v) => Some(v),
+            Cell::Empty => None,
+        }
+    }
+}
+
 impl fmt::Debug for Cell {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -17,234 +33,3595 @@ impl fmt::Debug for Cell {
     }
 }
 
+// Values are written as base 36 digits, so that boards larger than 9x9 can
+// still use one character per cell: 10 is `A`, 16 is `G` and so on.
+pub fn value_to_char(v: u8) -> char {
+    std::char::from_digit(u32::from(v), 36)
+        .expect("Values must be below 36")
+        .to_ascii_uppercase()
+}
+
+pub fn char_to_value(c: char) -> Option<u8> {
+    c.to_digit(36).map(|v| v as u8)
+}
+
+// The square root rounded down, without going through floating point.
+fn isqrt(n: usize) -> usize {
+    let mut root = 0;
+    while (root + 1) * (root + 1) <= n {
+        root += 1;
+    }
+    root
+}
+
+/// Solves a puzzle in the format of `Board::from_str_puzzle`, e.g. 81 digits
+/// for a 9x9 board with `0` or `.` for the empty cells. The solution comes
+/// back as digits too. None if the puzzle can't be read or has no solution.
+pub fn solve_puzzle(input: &str) -> Option<String> {
+    Board::from_str_puzzle(input.trim())
+        .ok()?
+        .solve()
+        .map(|solution| solution.to_string_puzzle())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Variant {
+    Classic,
+    // X-Sudoku: the two main diagonals can't repeat values either.
+    Diagonal,
+    // Cells a knight's move apart can't hold the same value.
+    AntiKnight,
+}
+
+/// A group of cells for killer sudoku: their values add up to `sum`, and none
+/// of them is repeated.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cage {
+    pub cells: Vec<(usize, usize)>,
+    pub sum: u32,
+}
+
 #[derive(Clone, PartialEq)]
 pub struct Board {
     pub squares: Box<[Cell]>,
     pub n: usize,
+    // Pencil marks for each cell. The solver doesn't care about these.
+    pub notes: Box<[HashSet<u8>]>,
+    // The color the player painted each cell with, if any. Also ignored by
+    // the solver.
+    pub colors: Box<[Option<u8>]>,
+    pub box_w: usize,
+    pub box_h: usize,
+    pub variant: Variant,
+    // Killer sudoku cages, on top of whatever the variant asks for.
+    pub cages: Vec<Cage>,
 }
 
 #[allow(dead_code)]
 impl Board {
+    /// An empty board with square boxes. The supported sizes are 1, 4, 9, 16
+    /// and 25; other sizes need `with_boxes`.
+    pub fn try_new(n: usize) -> Result<Board, String> {
+        let sqrt_n = isqrt(n);
+        if n == 0 || sqrt_n * sqrt_n != n || n > 25 {
+            return Err(format!("A {}x{} board can't have square boxes", n, n));
+        }
+        Ok(Board::with_boxes(n, sqrt_n, sqrt_n))
+    }
+
+    /// Like `try_new`, but panics on unsupported sizes.
     pub fn new(n: usize) -> Board {
+        Board::try_new(n).unwrap()
+    }
+
+    /// An empty board whose boxes are `box_w` cells wide and `box_h` cells
+    /// tall, e.g. 2 by 3 for a 6x6 board. Panics for boards of 32x32 and up,
+    /// which the solver's bitmasks can't hold.
+    pub fn with_boxes(n: usize, box_w: usize, box_h: usize) -> Board {
+        assert_eq!(box_w * box_h, n);
+        assert!(n < 32, "A {}x{} board is too large", n, n);
         Board {
             squares: vec![Cell::Empty; n * n].into_boxed_slice(),
-            n: n,
+            n,
+            notes: vec![HashSet::new(); n * n].into_boxed_slice(),
+            colors: vec![None; n * n].into_boxed_slice(),
+            box_w,
+            box_h,
+            variant: Variant::Classic,
+            cages: Vec::new(),
+        }
+    }
+
+    pub fn try_from(squares: &[Cell]) -> Result<Board, String> {
+        let n = isqrt(squares.len());
+        if n * n != squares.len() {
+            return Err(format!("A board of {} cells is not square", squares.len()));
+        }
+        // Without explicit box dimensions the boxes have to be square.
+        let mut board = Board::try_new(n)?;
+        for (i, cell) in squares.iter().enumerate() {
+            match cell.value() {
+                Some(v) if v == 0 || v as usize > n => {
+                    return Err(format!("Invalid value {} for cell {}", v, i))
+                }
+                _ => (),
+            }
         }
+        // TODO: there must be a nicer way to do this.
+        board.squares = squares.to_vec().into_boxed_slice();
+        Ok(board)
     }
 
+    /// Like `try_from`, but panics if the cells don't make a board with
+    /// square boxes.
     pub fn from(squares: &[Cell]) -> Board {
-        let n = (squares.len() as f64).sqrt() as usize;
-        assert_eq!(n * n, squares.len());
-        Board {
-            // TODO: there must be a nicer way to do this.
-            squares: squares.to_vec().into_boxed_slice(),
-            n: n,
+        Board::try_from(squares).unwrap()
+    }
+
+    /// Parses a puzzle written one digit per cell, row by row, where `0` or
+    /// `.` is an empty cell.
+    pub fn from_str_puzzle(s: &str) -> Result<Board, String> {
+        let len = s.chars().count();
+        let n = isqrt(len);
+        if n * n != len {
+            return Err(format!("A puzzle of {} cells is not square", len));
+        }
+
+        let mut squares = Vec::with_capacity(len);
+        for c in s.chars() {
+            let cell = match c {
+                '0' | '.' => Cell::Empty,
+                _ => match c.to_digit(10) {
+                    Some(v) if v as usize <= n => Cell::Constant(v as u8),
+                    Some(v) => return Err(format!("{} is too large for a {}x{} board", v, n, n)),
+                    None => return Err(format!("Invalid character '{}' in puzzle", c)),
+                },
+            };
+            squares.push(cell);
+        }
+        Board::try_from(&squares)
+    }
+
+    pub fn to_string_puzzle(&self) -> String {
+        self.squares
+            .iter()
+            .map(|cell| match cell {
+                Cell::Variable(v) | Cell::Constant(v) => format!("{}", v),
+                Cell::Empty => "0".to_string(),
+            })
+            .collect()
+    }
+
+    /// One character per cell, row by row, with `.` for an empty cell.
+    pub fn to_line(&self) -> String {
+        self.squares
+            .iter()
+            .map(|cell| match cell {
+                Cell::Variable(v) | Cell::Constant(v) => value_to_char(*v),
+                Cell::Empty => '.',
+            })
+            .collect()
+    }
+
+    /// The inverse of `to_line`, where all values become constants. Both `.`
+    /// and `0` are accepted as empty cells.
+    pub fn from_line(s: &str) -> Result<Board, String> {
+        let len = s.chars().count();
+        let n = isqrt(len);
+        if n * n != len {
+            return Err(format!("A line of {} cells is not square", len));
+        }
+
+        let mut squares = Vec::with_capacity(len);
+        for c in s.chars() {
+            let cell = match (c, char_to_value(c)) {
+                ('.', _) | (_, Some(0)) => Cell::Empty,
+                (_, Some(v)) if v as usize <= n => Cell::Constant(v),
+                _ => return Err(format!("Invalid cell '{}' for a {}x{} board", c, n, n)),
+            };
+            squares.push(cell);
+        }
+        Board::try_from(&squares)
+    }
+
+    /// The board as JSON, e.g. `{"n":4,"box_w":2,"box_h":2,"variant":"classic",
+    /// "cells":[{"kind":"constant","value":1},{"kind":"empty","value":null},...]}`
+    /// with the cells row by row, followed by any killer cages as e.g.
+    /// `"cages":[{"cells":[[0,0],[1,0]],"sum":3}]`. Notes are left out.
+    pub fn to_json(&self) -> String {
+        let cells: Vec<String> = self
+            .squares
+            .iter()
+            .zip(self.colors.iter())
+            .map(|(cell, color)| {
+                // Cells without a color leave it out.
+                let color = color.map_or(String::new(), |c| format!(r#","color":{}"#, c));
+                match cell {
+                    Cell::Constant(v) => format!(r#"{{"kind":"constant","value":{}{}}}"#, v, color),
+                    Cell::Variable(v) => format!(r#"{{"kind":"variable","value":{}{}}}"#, v, color),
+                    Cell::Empty => format!(r#"{{"kind":"empty","value":null{}}}"#, color),
+                }
+            })
+            .collect();
+        let variant = match self.variant {
+            Variant::Classic => "classic",
+            Variant::Diagonal => "diagonal",
+            Variant::AntiKnight => "anti-knight",
+        };
+        // Boards without cages leave them out.
+        let cages = if self.cages.is_empty() {
+            String::new()
+        } else {
+            let cages: Vec<String> = self
+                .cages
+                .iter()
+                .map(|cage| {
+                    let cells: Vec<String> = cage
+                        .cells
+                        .iter()
+                        .map(|(x, y)| format!("[{},{}]", x, y))
+                        .collect();
+                    format!(r#"{{"cells":[{}],"sum":{}}}"#, cells.join(","), cage.sum)
+                })
+                .collect();
+            format!(r#","cages":[{}]"#, cages.join(","))
+        };
+        format!(
+            r#"{{"n":{},"box_w":{},"box_h":{},"variant":"{}","cells":[{}]{}}}"#,
+            self.n,
+            self.box_w,
+            self.box_h,
+            variant,
+            cells.join(","),
+            cages
+        )
+    }
+
+    /// The inverse of `to_json`. Everything but `n` and `cells` may be left
+    /// out, giving square boxes, the classic rules, no colors and no cages. A
+    /// cell's value may also be given as `v`, e.g. `{"v":5,"kind":"constant"}`.
+    pub fn from_json(s: &str) -> Result<Board, String> {
+        let json = Json::parse(s)?;
+        let n = match json.get("n") {
+            Some(Json::Number(n)) if n.fract() == 0.0 && *n >= 1.0 && *n <= 25.0 => *n as usize,
+            _ => return Err("Expected a board size from 1 to 25 in \"n\"".to_string()),
+        };
+        let cells = match json.get("cells") {
+            Some(Json::Array(cells)) if cells.len() == n * n => cells,
+            _ => return Err(format!("Expected {} cells in \"cells\"", n * n)),
+        };
+
+        let mut board = match (json.get("box_w"), json.get("box_h")) {
+            (None, None) => Board::try_new(n)?,
+            (Some(Json::Number(w)), Some(Json::Number(h)))
+                if w.fract() == 0.0
+                    && h.fract() == 0.0
+                    && *w >= 1.0
+                    && *h >= 1.0
+                    && w * h == n as f64 =>
+            {
+                Board::with_boxes(n, *w as usize, *h as usize)
+            }
+            _ => return Err(format!("Invalid boxes for a {}x{} board", n, n)),
+        };
+        board.variant = match json.get("variant") {
+            None => Variant::Classic,
+            Some(Json::String(variant)) if variant == "classic" => Variant::Classic,
+            Some(Json::String(variant)) if variant == "diagonal" => Variant::Diagonal,
+            Some(Json::String(variant)) if variant == "anti-knight" => Variant::AntiKnight,
+            Some(_) => return Err("Unknown variant".to_string()),
+        };
+        for (i, cell) in cells.iter().enumerate() {
+            let value = match cell.get("value").or_else(|| cell.get("v")) {
+                Some(Json::Number(v)) if v.fract() == 0.0 && *v >= 1.0 && *v <= n as f64 => {
+                    Some(*v as u8)
+                }
+                Some(Json::Null) | None => None,
+                Some(_) => return Err(format!("Invalid value for cell {}", i)),
+            };
+            board.squares[i] = match (cell.get("kind"), value) {
+                (Some(Json::String(kind)), Some(v)) if kind == "constant" => Cell::Constant(v),
+                (Some(Json::String(kind)), Some(v)) if kind == "variable" => Cell::Variable(v),
+                (Some(Json::String(kind)), None) if kind == "empty" => Cell::Empty,
+                _ => return Err(format!("Invalid cell {}", i)),
+            };
+            board.colors[i] = match cell.get("color") {
+                Some(Json::Number(c)) if c.fract() == 0.0 && *c >= 0.0 && *c <= 255.0 => {
+                    Some(*c as u8)
+                }
+                Some(Json::Null) | None => None,
+                Some(_) => return Err(format!("Invalid color for cell {}", i)),
+            };
+        }
+        board.cages = match json.get("cages") {
+            None => Vec::new(),
+            Some(Json::Array(cages)) => {
+                let mut parsed = Vec::new();
+                for (i, cage) in cages.iter().enumerate() {
+                    match Board::cage_from_json(cage, n) {
+                        Some(cage) => parsed.push(cage),
+                        None => return Err(format!("Invalid cage {}", i)),
+                    }
+                }
+                parsed
+            }
+            Some(_) => return Err("Expected a list of cages in \"cages\"".to_string()),
+        };
+        Ok(board)
+    }
+
+    // A cage as written by `to_json`, with every cell on an n by n board.
+    fn cage_from_json(json: &Json, n: usize) -> Option<Cage> {
+        let is_index = |i: &f64| i.fract() == 0.0 && *i >= 0.0 && *i < n as f64;
+        let cells = match json.get("cells") {
+            Some(Json::Array(cells)) => cells
+                .iter()
+                .map(|cell| match cell {
+                    Json::Array(xy) => match xy.as_slice() {
+                        [Json::Number(x), Json::Number(y)] if is_index(x) && is_index(y) => {
+                            Some((*x as usize, *y as usize))
+                        }
+                        _ => None,
+                    },
+                    _ => None,
+                })
+                .collect::<Option<Vec<(usize, usize)>>>()?,
+            _ => return None,
+        };
+        let sum = match json.get("sum") {
+            Some(Json::Number(sum))
+                if sum.fract() == 0.0 && *sum >= 0.0 && *sum <= f64::from(u32::MAX) =>
+            {
+                *sum as u32
+            }
+            _ => return None,
+        };
+        Some(Cage { cells, sum })
+    }
+
+    /// Reads the puzzle in a URL hash like `#53..7....`, in the line format.
+    /// A hash without a puzzle gives `None`.
+    pub fn from_url_hash(hash: &str) -> Result<Option<Board>, String> {
+        let line = hash.trim_start_matches('#').trim();
+        if line.is_empty() {
+            Ok(None)
+        } else {
+            Board::from_line(line).map(Some)
+        }
+    }
+
+    /// Reads a value typed by the player, either as a number or as its symbol
+    /// (`A` for 10 and so on). Values that don't fit the board are rejected.
+    pub fn parse_value(&self, s: &str) -> Option<u8> {
+        let mut chars = s.chars();
+        let v = match (s.parse::<u8>(), chars.next(), chars.next()) {
+            (Ok(v), _, _) => v,
+            (Err(_), Some(c), None) => char_to_value(c)?,
+            _ => return None,
+        };
+        if self.is_valid_value(v) {
+            Some(v)
+        } else {
+            None
         }
     }
 
+    /// Whether a cell of this board can hold the value, from 1 to n. 0 is
+    /// never a value, empty cells are `Cell::Empty`.
+    pub fn is_valid_value(&self, v: u8) -> bool {
+        v >= 1 && v as usize <= self.n
+    }
+
     pub fn get(&self, x: usize, y: usize) -> Cell {
         self.squares[y * self.n + x]
     }
 
+    /// Like `get`, but None if (x, y) is outside the board.
+    pub fn get_checked(&self, x: usize, y: usize) -> Option<Cell> {
+        if x < self.n && y < self.n {
+            Some(self.get(x, y))
+        } else {
+            None
+        }
+    }
+
+    /// Like `set`, but None if (x, y) is outside the board.
+    pub fn set_checked(&self, x: usize, y: usize, v: Cell) -> Option<Board> {
+        if x < self.n && y < self.n {
+            Some(self.set(x, y, v))
+        } else {
+            None
+        }
+    }
+
     pub fn set(&self, x: usize, y: usize, v: Cell) -> Board {
-        let mut board = Board {
-            squares: self.squares.to_vec().into_boxed_slice(),
-            n: self.n,
-        };
-        board.squares[y * self.n + x] = v;
+        let mut board = self.clone();
+        board.set_mut(x, y, v);
         board
     }
 
-    fn check_row_constraint(&self, y: usize) -> bool {
-        let mut set: HashSet<u8> = HashSet::new();
-        for x in 0..self.n {
-            let value = match self.get(x, y) {
-                Cell::Variable(v) | Cell::Constant(v) => v,
-                Cell::Empty => continue,
-            };
-            match set.get(&value) {
-                Some(_) => return false,
-                None => set.insert(value),
-            };
+    pub fn set_mut(&mut self, x: usize, y: usize, v: Cell) {
+        self.squares[y * self.n + x] = v;
+        if v != Cell::Empty {
+            self.notes[y * self.n + x].clear();
         }
-        true
     }
 
-    fn check_col_constraint(&self, x: usize) -> bool {
-        let mut set: HashSet<u8> = HashSet::new();
+    /// Puts the player's value in (x, y), or clears it for None. The givens
+    /// can't be changed this way, so the board is returned as it is for them.
+    pub fn enter(&self, x: usize, y: usize, value: Option<u8>) -> Board {
+        if self.get(x, y).is_constant() {
+            return self.clone();
+        }
+        match value {
+            Some(v) => self.set(x, y, Cell::Variable(v)),
+            None => self.set(x, y, Cell::Empty),
+        }
+    }
+
+    pub fn get_color(&self, x: usize, y: usize) -> Option<u8> {
+        self.colors[y * self.n + x]
+    }
+
+    /// Paints the cell, or takes its color away for None. Any cell can be
+    /// painted, filled or not.
+    pub fn set_color(&self, x: usize, y: usize, color: Option<u8>) -> Board {
+        let mut board = self.clone();
+        board.colors[y * self.n + x] = color;
+        board
+    }
+
+    pub fn get_notes(&self, x: usize, y: usize) -> Vec<u8> {
+        let mut notes: Vec<u8> = self.notes[y * self.n + x].iter().cloned().collect();
+        notes.sort();
+        notes
+    }
+
+    /// Adds or removes a pencil mark. Only empty cells can have notes.
+    pub fn toggle_note(&self, x: usize, y: usize, v: u8) -> Board {
+        let mut board = self.clone();
+        if self.get(x, y) == Cell::Empty {
+            let notes = &mut board.notes[y * self.n + x];
+            if !notes.remove(&v) {
+                notes.insert(v);
+            }
+        }
+        board
+    }
+
+    /// Pencils every empty cell's candidates into its notes, replacing the
+    /// notes it had.
+    pub fn fill_notes(&self) -> Board {
+        let mut board = self.clone();
         for y in 0..self.n {
-            let value = match self.get(x, y) {
-                Cell::Variable(v) | Cell::Constant(v) => v,
-                Cell::Empty => continue,
-            };
-            match set.get(&value) {
-                Some(_) => return false,
-                None => set.insert(value),
-            };
+            for x in 0..self.n {
+                board.notes[y * self.n + x] = self.candidates(x, y).into_iter().collect();
+            }
         }
-        true
+        board
     }
 
-    fn check_box_constraint(&self, x: usize, y: usize) -> bool {
-        let mut set: HashSet<u8> = HashSet::new();
-        let sqrt_n = (self.n as f64).sqrt() as usize;
-        for y_ in (y / sqrt_n * sqrt_n)..((y / sqrt_n + 1) * sqrt_n) {
-            for x_ in (x / sqrt_n * sqrt_n)..((x / sqrt_n + 1) * sqrt_n) {
-                let value = match self.get(x_, y_) {
-                    Cell::Variable(v) | Cell::Constant(v) => v,
-                    Cell::Empty => continue,
-                };
-                match set.get(&value) {
-                    Some(_) => return false,
-                    None => set.insert(value),
-                };
+    /// Removes the value in (x, y) from the notes of its peers, which can't
+    /// hold it anymore.
+    pub fn prune_notes(&self, x: usize, y: usize) -> Board {
+        let mut board = self.clone();
+        if let Some(v) = self.get(x, y).value() {
+            for (x_, y_, _) in self.iter_cells() {
+                if self.is_peer((x_, y_), (x, y)) {
+                    board.notes[y_ * self.n + x_].remove(&v);
+                }
+            }
+        }
+        board
+    }
+
+    /// An empty board of the same size, boxes and variant.
+    pub fn clear(&self) -> Board {
+        let mut board = Board::with_boxes(self.n, self.box_w, self.box_h);
+        board.variant = self.variant;
+        board.cages = self.cages.clone();
+        board
+    }
+
+    /// Removes the player's entries and notes, so the puzzle can be started
+    /// over. The constants and the colors stay.
+    pub fn clear_variables(&self) -> Board {
+        let mut board = self.clone();
+        for (cell, notes) in board.squares.iter_mut().zip(board.notes.iter_mut()) {
+            if let Cell::Variable(_) = cell {
+                *cell = Cell::Empty;
+            }
+            notes.clear();
+        }
+        board
+    }
+
+    /// The board mirrored along its main diagonal, so rows become columns.
+    pub fn transpose(&self) -> Board {
+        self.remap(self.box_h, self.box_w, |x, y| (y, x))
+    }
+
+    /// The board turned a quarter clockwise.
+    pub fn rotate_90(&self) -> Board {
+        self.remap(self.box_h, self.box_w, |x, y| self.rotate_cell_90(x, y))
+    }
+
+    /// Where the cell (x, y) ends up when the board is turned by `rotate_90`.
+    pub fn rotate_cell_90(&self, x: usize, y: usize) -> (usize, usize) {
+        (self.n - 1 - y, x)
+    }
+
+    /// The board turned upside down.
+    pub fn rotate_180(&self) -> Board {
+        let n = self.n;
+        self.remap(self.box_w, self.box_h, |x, y| (n - 1 - x, n - 1 - y))
+    }
+
+    // Moves every cell, with its notes, from (x, y) to `to(x, y)` on a board
+    // with the given boxes. `to` has to map the units of this board onto
+    // those of the new one.
+    fn remap<F>(&self, box_w: usize, box_h: usize, to: F) -> Board
+    where
+        F: Fn(usize, usize) -> (usize, usize),
+    {
+        let mut board = Board::with_boxes(self.n, box_w, box_h);
+        board.variant = self.variant;
+        board.cages = self.cages.clone();
+        for cage in board.cages.iter_mut() {
+            for cell in cage.cells.iter_mut() {
+                *cell = to(cell.0, cell.1);
+            }
+        }
+        for y in 0..self.n {
+            for x in 0..self.n {
+                let (x_, y_) = to(x, y);
+                board.squares[y_ * self.n + x_] = self.get(x, y);
+                board.notes[y_ * self.n + x_] = self.notes[y * self.n + x].clone();
+                board.colors[y_ * self.n + x_] = self.colors[y * self.n + x];
+            }
+        }
+        board
+    }
+
+    /// The cells of row y, from left to right.
+    pub fn row(&self, y: usize) -> Vec<Cell> {
+        self.squares[y * self.n..(y + 1) * self.n].to_vec()
+    }
+
+    /// The cells of column x, from top to bottom.
+    pub fn col(&self, x: usize) -> Vec<Cell> {
+        (0..self.n).map(|y| self.get(x, y)).collect()
+    }
+
+    /// The cells of the box containing (x, y), row by row.
+    pub fn box_cells(&self, x: usize, y: usize) -> Vec<Cell> {
+        (0..self.n)
+            .map(|i| {
+                let (box_x, box_y) = self.box_cell(x, y, i);
+                self.get(box_x, box_y)
+            })
+            .collect()
+    }
+
+    // Whether no value is repeated among the cells. The values seen so far
+    // fit in a bitmask.
+    fn all_different<I>(cells: I) -> bool
+    where
+        I: IntoIterator<Item = Cell>,
+    {
+        let mut seen: u32 = 0;
+        for cell in cells {
+            if let Cell::Variable(v) | Cell::Constant(v) = cell {
+                if seen & (1 << v) != 0 {
+                    return false;
+                }
+                seen |= 1 << v;
             }
         }
         true
     }
 
+    fn check_row_constraint(&self, y: usize) -> bool {
+        Board::all_different(self.row(y))
+    }
+
+    fn check_col_constraint(&self, x: usize) -> bool {
+        Board::all_different(self.col(x))
+    }
+
+    // The i-th cell of the box containing (x, y).
+    fn box_cell(&self, x: usize, y: usize, i: usize) -> (usize, usize) {
+        (
+            x / self.box_w * self.box_w + i % self.box_w,
+            y / self.box_h * self.box_h + i / self.box_w,
+        )
+    }
+
+    fn check_box_constraint(&self, x: usize, y: usize) -> bool {
+        Board::all_different(self.box_cells(x, y))
+    }
+
+    // The i-th cell of the main diagonal if d is 0, or of the anti-diagonal
+    // if d is 1.
+    fn diagonal_cell(&self, d: usize, i: usize) -> (usize, usize) {
+        if d == 0 {
+            (i, i)
+        } else {
+            (self.n - 1 - i, i)
+        }
+    }
+
+    // The diagonals the cell lies on. Only the diagonal variant has any.
+    fn diagonals_of(&self, x: usize, y: usize) -> Vec<usize> {
+        if self.variant != Variant::Diagonal {
+            return Vec::new();
+        }
+        (0..2)
+            .filter(|&d| self.diagonal_cell(d, y) == (x, y))
+            .collect()
+    }
+
+    fn check_diagonal_constraint(&self) -> bool {
+        (0..2).all(|d| {
+            Board::all_different((0..self.n).map(|i| {
+                let (x, y) = self.diagonal_cell(d, i);
+                self.get(x, y)
+            }))
+        })
+    }
+
+    // The cells a knight's move away. Only the anti-knight variant has any.
+    fn knight_cells(&self, x: usize, y: usize) -> Vec<(usize, usize)> {
+        if self.variant != Variant::AntiKnight {
+            return Vec::new();
+        }
+        let moves = [
+            (1, 2),
+            (2, 1),
+            (2, -1),
+            (1, -2),
+            (-1, -2),
+            (-2, -1),
+            (-2, 1),
+            (-1, 2),
+        ];
+        let n = self.n as isize;
+        moves
+            .iter()
+            .map(|&(dx, dy)| (x as isize + dx, y as isize + dy))
+            .filter(|&(x_, y_)| x_ >= 0 && x_ < n && y_ >= 0 && y_ < n)
+            .map(|(x_, y_)| (x_ as usize, y_ as usize))
+            .collect()
+    }
+
+    fn check_knight_constraint(&self, x: usize, y: usize) -> bool {
+        let value = match self.get(x, y) {
+            Cell::Variable(v) | Cell::Constant(v) => v,
+            Cell::Empty => return true,
+        };
+        self.knight_cells(x, y)
+            .iter()
+            .all(|&(x_, y_)| self.get(x_, y_).value() != Some(value))
+    }
+
+    // The cages the cell is part of.
+    fn cages_of(&self, x: usize, y: usize) -> Vec<&Cage> {
+        self.cages
+            .iter()
+            .filter(|cage| cage.cells.contains(&(x, y)))
+            .collect()
+    }
+
+    // Whether the values can be part of a cage of `size` cells adding up to
+    // `sum`: none of them is repeated, and the cells left empty can still make
+    // up the difference. k different values add up to at least 1 + ... + k.
+    fn cage_fits(values: &[u8], size: usize, sum: u32) -> bool {
+        if !Board::all_different(values.iter().map(|&v| Cell::Variable(v))) {
+            return false;
+        }
+        let total: u32 = values.iter().map(|&v| u32::from(v)).sum();
+        let left = (size - values.len()) as u32;
+        if left == 0 {
+            total == sum
+        } else {
+            total + left * (left + 1) / 2 <= sum
+        }
+    }
+
+    // The values filled in so far in the cage, apart from the one at `skip`.
+    fn cage_values(&self, cage: &Cage, skip: Option<(usize, usize)>) -> Vec<u8> {
+        cage.cells
+            .iter()
+            .filter(|&&cell| Some(cell) != skip)
+            .filter_map(|&(x, y)| self.get(x, y).value())
+            .collect()
+    }
+
+    fn check_cage_constraint(&self, cage: &Cage) -> bool {
+        Board::cage_fits(&self.cage_values(cage, None), cage.cells.len(), cage.sum)
+    }
+
+    // Whether v can go in the cell (x, y) as far as its cages go.
+    fn cages_allow(&self, x: usize, y: usize, v: u8) -> bool {
+        self.cages_of(x, y).into_iter().all(|cage| {
+            let mut values = self.cage_values(cage, Some((x, y)));
+            values.push(v);
+            Board::cage_fits(&values, cage.cells.len(), cage.sum)
+        })
+    }
+
     fn within_constraints(&self, x: usize, y: usize) -> bool {
         self.check_row_constraint(y)
             && self.check_col_constraint(x)
             && self.check_box_constraint(x, y)
+            && (self.variant != Variant::Diagonal || self.check_diagonal_constraint())
+            && self.check_knight_constraint(x, y)
+            && self
+                .cages_of(x, y)
+                .into_iter()
+                .all(|cage| self.check_cage_constraint(cage))
     }
 
-    fn solver(&self, x: usize, y: usize) -> Option<Board> {
-        let x_next = if x < self.n - 1 { x + 1 } else { 0 };
-        let y_next = if x < self.n - 1 { y } else { y + 1 };
-
-        match self.get(x, y) {
-            Cell::Constant(_) => {
-                if !self.within_constraints(x, y) {
-                    return None;
-                } else if x == self.n - 1 && y == self.n - 1 {
-                    // We have finished.
-                    return Some(Board {
-                        squares: self.squares.to_vec().into_boxed_slice(),
-                        n: self.n,
-                    });
+    pub fn is_valid(&self) -> bool {
+        for i in 0..self.n {
+            if !self.check_row_constraint(i) || !self.check_col_constraint(i) {
+                return false;
+            }
+            // The i-th box, counted left-to-right, top-to-bottom. There are
+            // n / box_w = box_h boxes side by side.
+            if !self.check_box_constraint(i % self.box_h * self.box_w, i / self.box_h * self.box_h)
+            {
+                return false;
+            }
+        }
+        if self.variant == Variant::AntiKnight {
+            for y in 0..self.n {
+                for x in 0..self.n {
+                    if !self.check_knight_constraint(x, y) {
+                        return false;
+                    }
                 }
-                self.solver(x_next, y_next)
             }
-            _ => {
-                for v in 1..=self.n {
-                    let new_board = self.set(x, y, Cell::Variable(v as u8));
+        }
+        self.cages
+            .iter()
+            .all(|cage| self.check_cage_constraint(cage))
+            && (self.variant != Variant::Diagonal || self.check_diagonal_constraint())
+    }
 
-                    if !new_board.within_constraints(x, y) {
-                        continue;
-                    }
+    pub fn empty_count(&self) -> usize {
+        self.squares
+            .iter()
+            .filter(|&&cell| cell == Cell::Empty)
+            .count()
+    }
 
-                    if x == self.n - 1 && y == self.n - 1 {
-                        // We have finished.
-                        return Some(Board {
-                            squares: new_board.squares.to_vec().into_boxed_slice(),
-                            n: self.n,
-                        });
-                    }
+    /// How many times each value is on the board, indexed by value. Index 0
+    /// is always 0.
+    pub fn placed_counts(&self) -> Vec<usize> {
+        let mut counts = vec![0; self.n + 1];
+        for cell in self.squares.iter() {
+            if let Some(v) = cell.value() {
+                counts[v as usize] += 1;
+            }
+        }
+        counts
+    }
 
-                    match new_board.solver(x_next, y_next) {
-                        Some(board) => return Some(board),
-                        _ => (),
+    /// Every cell with its position, row by row.
+    pub fn iter_cells<'a>(&'a self) -> impl Iterator<Item = (usize, usize, Cell)> + 'a {
+        let n = self.n;
+        self.squares
+            .iter()
+            .enumerate()
+            .map(move |(i, &cell)| (i % n, i / n, cell))
+    }
+
+    /// The clues of the puzzle: every constant with its position, row by row.
+    pub fn givens(&self) -> Vec<(usize, usize, u8)> {
+        self.iter_cells()
+            .filter_map(|(x, y, cell)| match cell {
+                Cell::Constant(v) => Some((x, y, v)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Whether every cell is filled in without breaking any constraint.
+    pub fn is_solved(&self) -> bool {
+        !self.squares.contains(&Cell::Empty) && self.is_valid()
+    }
+
+    fn has_conflict(&self, x: usize, y: usize) -> bool {
+        let value = match self.get(x, y) {
+            Cell::Variable(v) | Cell::Constant(v) => v,
+            Cell::Empty => return false,
+        };
+        let same = |x_: usize, y_: usize| match self.get(x_, y_) {
+            Cell::Variable(v) | Cell::Constant(v) => (x_, y_) != (x, y) && v == value,
+            Cell::Empty => false,
+        };
+        let diagonals = self.diagonals_of(x, y);
+        (0..self.n).any(|i| {
+            let (box_x, box_y) = self.box_cell(x, y, i);
+            same(i, y)
+                || same(x, i)
+                || same(box_x, box_y)
+                || diagonals.iter().any(|&d| {
+                    let (diagonal_x, diagonal_y) = self.diagonal_cell(d, i);
+                    same(diagonal_x, diagonal_y)
+                })
+        }) || !self.check_knight_constraint(x, y)
+            || self
+                .cages_of(x, y)
+                .into_iter()
+                .any(|cage| !self.check_cage_constraint(cage))
+    }
+
+    /// Whether two cells share a row, column, box, cage or, for the diagonal
+    /// variant, a diagonal. In the anti-knight variant, cells a knight's
+    /// move apart are peers too.
+    pub fn is_peer(&self, (x, y): (usize, usize), (x_, y_): (usize, usize)) -> bool {
+        x == x_
+            || y == y_
+            || (x / self.box_w == x_ / self.box_w && y / self.box_h == y_ / self.box_h)
+            || self
+                .diagonals_of(x, y)
+                .iter()
+                .any(|d| self.diagonals_of(x_, y_).contains(d))
+            || self.knight_cells(x, y).contains(&(x_, y_))
+            || self
+                .cages_of(x, y)
+                .iter()
+                .any(|cage| cage.cells.contains(&(x_, y_)))
+    }
+
+    pub fn cell_is_valid(&self, x: usize, y: usize) -> bool {
+        !self.has_conflict(x, y)
+    }
+
+    pub fn find_conflict(&self) -> Option<(usize, usize)> {
+        for y in 0..self.n {
+            for x in 0..self.n {
+                if let Cell::Constant(_) = self.get(x, y) {
+                    if self.has_conflict(x, y) {
+                        return Some((x, y));
                     }
                 }
-                None
             }
         }
+        None
     }
 
-    pub fn solve(&self) -> Option<Board> {
-        self.solver(0, 0)
+    pub fn conflicts(&self) -> Vec<(usize, usize)> {
+        let mut conflicts = Vec::new();
+        for y in 0..self.n {
+            for x in 0..self.n {
+                if self.has_conflict(x, y) {
+                    conflicts.push((x, y));
+                }
+            }
+        }
+        conflicts
     }
-}
 
-impl fmt::Debug for Board {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    /// The solution of the constants, if they have exactly one.
+    pub fn unique_solution(&self) -> Option<Board> {
+        let mut solutions = self.solutions();
+        match (solutions.next(), solutions.next()) {
+            (Some(solution), None) => Some(solution),
+            _ => None,
+        }
+    }
+
+    /// Like `unique_solution`, but gives up once the search has visited more
+    /// than `max_nodes` cells in all, see `solve_bounded`.
+    pub fn unique_solution_bounded(
+        &self,
+        max_nodes: usize,
+    ) -> Result<Option<Board>, BudgetExceeded> {
+        let mut search = Search::new(self, None);
+        let first = search.next_solution_bounded(max_nodes)?;
+        match (first, search.next_solution_bounded(max_nodes)?) {
+            (Some(solution), None) => Ok(Some(solution)),
+            _ => Ok(None),
+        }
+    }
+
+    /// The player's entries that are known to be wrong. When the constants
+    /// have a unique solution, these are the variables that disagree with
+    /// it. Otherwise only the variables that break a constraint are.
+    pub fn incorrect_cells(&self) -> Vec<(usize, usize)> {
+        self.incorrect_cells_from(self.unique_solution().as_ref())
+    }
+
+    /// Like `incorrect_cells`, with the unique solution of the constants
+    /// already worked out, or None if they don't have one.
+    pub fn incorrect_cells_from(&self, solution: Option<&Board>) -> Vec<(usize, usize)> {
+        let mut incorrect = Vec::new();
         for y in 0..self.n {
             for x in 0..self.n {
-                match write!(f, " {:?} ", self.get(x, y)) {
-                    Err(e) => return Err(e),
-                    _ => (),
+                if let Cell::Variable(v) = self.get(x, y) {
+                    let wrong = match solution {
+                        Some(solution) => solution.get(x, y).value() != Some(v),
+                        None => self.has_conflict(x, y),
+                    };
+                    if wrong {
+                        incorrect.push((x, y));
+                    }
                 }
             }
-            match write!(f, "\n") {
-                Err(e) => return Err(e),
-                _ => (),
-            }
         }
-        write!(f, "")
+        incorrect
     }
-}
+
+    /// The values that can be placed in the cell without breaking a
+    /// constraint. Cells that are already filled have no candidates.
+    pub fn candidates(&self, x: usize, y: usize) -> Vec<u8> {
+        if self.get(x, y) != Cell::Empty {
+            return Vec::new();
+        }
+        let mut seen = vec![false; self.n + 1];
+        let diagonals = self.diagonals_of(x, y);
+        for i in 0..self.n {
+            let (box_x, box_y) = self.box_cell(x, y, i);
+            let peers = [self.get(i, y), self.get(x, i), self.get(box_x, box_y)];
+            for cell in peers.iter() {
+                if let Cell::Variable(v) | Cell::Constant(v) = cell {
+                    seen[*v as usize] = true;
+                }
+            }
+            for &d in diagonals.iter() {
+                let (diagonal_x, diagonal_y) = self.diagonal_cell(d, i);
+                if let Cell::Variable(v) | Cell::Constant(v) = self.get(diagonal_x, diagonal_y) {
+                    seen[v as usize] = true;
+                }
+            }
+        }
+        for (knight_x, knight_y) in self.knight_cells(x, y) {
+            if let Cell::Variable(v) | Cell::Constant(v) = self.get(knight_x, knight_y) {
+                seen[v as usize] = true;
+            }
+        }
+        (1..=self.n as u8)
+            .filter(|&v| !seen[v as usize] && self.cages_allow(x, y, v))
+            .collect()
+    }
+
+    /// Repeatedly fills in every empty cell that has only one legal value,
+    /// and the hidden singles once there are no such cells left. Returns false
+    /// if some empty cell is left without any legal value.
+    pub fn propagate(&mut self) -> bool {
+        loop {
+            let mut progress = false;
+            for y in 0..self.n {
+                for x in 0..self.n {
+                    if self.get(x, y) != Cell::Empty {
+                        continue;
+                    }
+                    let values = self.candidates(x, y);
+                    match values.len() {
+                        0 => return false,
+                        1 => {
+                            self.set_mut(x, y, Cell::Variable(values[0]));
+                            progress = true;
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            if !progress {
+                match self.apply_hidden_singles() {
+                    Some(board) => *self = board,
+                    None => return true,
+                }
+            }
+        }
+    }
+
+    // The empty cell with the fewest legal values, i.e. the one we are least
+    // likely to guess wrong on.
+    fn next_cell(&self) -> Option<(usize, usize)> {
+        let mut best: Option<((usize, usize), usize)> = None;
+        for y in 0..self.n {
+            for x in 0..self.n {
+                if self.get(x, y) != Cell::Empty {
+                    continue;
+                }
+                let count = self.candidates(x, y).len();
+                if count == 0 {
+                    return Some((x, y));
+                }
+                match best {
+                    Some((_, c)) if c <= count => (),
+                    _ => best = Some(((x, y), count)),
+                }
+            }
+        }
+        best.map(|(cell, _)| cell)
+    }
+
+    /// A solved copy of the board, which itself is left alone, so hints and
+    /// checks can peek at the solution without wiping the player's entries.
+    /// Everything the solver fills in is a `Cell::Variable`, so it can be
+    /// told apart from the constants.
+    pub fn solve(&self) -> Option<Board> {
+        self.solve_checked().ok()
+    }
+
+    /// Like `solve`, but tells a puzzle whose constants already conflict apart
+    /// from one that merely has no solution.
+    pub fn solve_checked(&self) -> Result<Board, SolveError> {
+        // The player's entries are thrown away by the search, so only the
+        // constants conflicting with each other make the input invalid.
+        if let Some((x, y)) = self.clear_variables().find_conflict() {
+            return Err(SolveError::InvalidInput(x, y));
+        }
+        self.solve_with_stats().0.ok_or(SolveError::Unsolvable)
+    }
+
+    /// Splits the search at the empty cell with the fewest candidates, once
+    /// the naked and hidden singles are filled in: one board for every value
+    /// the cell can hold, with that value filled in as a constant. Together
+    /// they have the same solutions as this board.
+    pub fn branches(&self) -> Vec<Board> {
+        let mut board = self.clear_variables();
+        if !board.propagate() {
+            return Vec::new();
+        }
+        match board.next_cell() {
+            Some((x, y)) => board
+                .candidates(x, y)
+                .into_iter()
+                .map(|v| board.set(x, y, Cell::Constant(v)))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Like `solve`, but searches the `branches` on a thread each and returns
+    /// whichever solution is found first, so on a puzzle with several
+    /// solutions it may not be the one `solve` gives. Only available natively,
+    /// as threads work differently in wasm.
+    #[cfg(all(feature = "threads", not(target_arch = "wasm32")))]
+    pub fn solve_parallel(&self) -> Option<Board> {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::{mpsc, Arc};
+        use std::thread;
+
+        let branches = self.branches();
+        if branches.is_empty() {
+            return self.solve();
+        }
+        // The first branch to finish with a solution wins, and tells the
+        // others to give up.
+        let stop = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = mpsc::channel();
+        let count = branches.len();
+        for branch in branches {
+            let stop = Arc::clone(&stop);
+            let sender = sender.clone();
+            thread::spawn(move || {
+                let mut search = Search::new(&branch, None);
+                while !stop.load(Ordering::Relaxed) {
+                    let solution = match search.step() {
+                        StepResult::Done => Some(search.board.clone()),
+                        StepResult::Failed => None,
+                        StepResult::Placed(..) | StepResult::Backtracked(..) => continue,
+                    };
+                    let _ = sender.send(solution);
+                    return;
+                }
+            });
+        }
+        let board = receiver.iter().take(count).flatten().next()?;
+        stop.store(true, Ordering::Relaxed);
+        Some(self.fill_from(&board))
+    }
+
+    /// Fills every cell that isn't a constant with its value in `solution`,
+    /// the way `solve` would have.
+    pub fn fill_from(&self, solution: &Board) -> Board {
+        let mut board = self.clone();
+        for (i, cell) in board.squares.iter_mut().enumerate() {
+            if !cell.is_constant() {
+                *cell = match solution.squares[i].value() {
+                    Some(v) => Cell::Variable(v),
+                    None => Cell::Empty,
+                };
+            }
+        }
+        board
+    }
+
+    /// Advances the search in `state` by a single step, so that it can be
+    /// watched as it goes. Looping until `Done` gives the same solution as
+    /// `solve`.
+    pub fn solve_step(state: &mut SolverState) -> StepResult {
+        state.search.step()
+    }
+
+    /// Like `solve`, but gives up once the search has visited more than
+    /// `max_nodes` cells (see `SolveStats::nodes`), so that a hopeless board
+    /// can't keep the page busy forever.
+    pub fn solve_bounded(&self, max_nodes: usize) -> Result<Option<Board>, BudgetExceeded> {
+        Search::new(self, None).next_solution_bounded(max_nodes)
+    }
+
+    /// Like `solve`, but also tells how much searching it took.
+    pub fn solve_with_stats(&self) -> (Option<Board>, SolveStats) {
+        let mut search = Search::new(self, None);
+        let solution = search.next_solution();
+        (solution, search.stats)
+    }
+
+    /// The position and value of the first empty cell in the solution of the
+    /// board, if it has one.
+    pub fn hint(&self) -> Option<(usize, usize, u8)> {
+        self.hint_at(None)
+    }
+
+    /// Like `hint`, but reveals the `preferred` cell instead if it is empty.
+    pub fn hint_at(&self, preferred: Option<(usize, usize)>) -> Option<(usize, usize, u8)> {
+        self.hint_from(&self.solve()?, preferred)
+    }
+
+    /// Like `hint_at`, taking the value from a solution already worked out.
+    pub fn hint_from(
+        &self,
+        solution: &Board,
+        preferred: Option<(usize, usize)>,
+    ) -> Option<(usize, usize, u8)> {
+        let i = match preferred {
+            Some((x, y)) if self.get(x, y) == Cell::Empty => y * self.n + x,
+            _ => self.squares.iter().position(|&cell| cell == Cell::Empty)?,
+        };
+        match solution.squares[i] {
+            Cell::Variable(v) | Cell::Constant(v) => Some((i % self.n, i / self.n, v)),
+            Cell::Empty => None,
+        }
+    }
+
+    /// Counts the solutions of the board, but stops searching once `limit`
+    /// solutions have been found.
+    pub fn count_solutions(&self, limit: usize) -> usize {
+        self.solutions().take(limit).count()
+    }
+
+    /// Whether the constants have exactly one solution, as a well made puzzle
+    /// should. A board whose constants already conflict has none.
+    pub fn has_unique_solution(&self) -> bool {
+        self.count_solutions(2) == 1
+    }
+
+    /// Every solution of the board. They are found one at a time, as they
+    /// are asked for.
+    pub fn solutions(&self) -> impl Iterator<Item = Board> {
+        Search::new(self, None)
+    }
+
+    /// Rates the board by how many guesses the solver has to make once there
+    /// are no naked or hidden singles left. A puzzle that singles alone solve
+    /// is easy, up to ten guesses is medium, up to a hundred is hard and
+    /// anything more is evil.
+    pub fn difficulty(&self) -> Difficulty {
+        Board::rate(self.solve_with_stats().1.guesses)
+    }
+
+    /// Like `difficulty`, but gives up once the search has visited more than
+    /// `max_nodes` cells, see `solve_bounded`.
+    pub fn difficulty_bounded(&self, max_nodes: usize) -> Result<Difficulty, BudgetExceeded> {
+        let mut search = Search::new(self, None);
+        search.next_solution_bounded(max_nodes)?;
+        Ok(Board::rate(search.stats.guesses))
+    }
+
+    // The difficulty of a board the solver needed this many guesses for.
+    fn rate(guesses: usize) -> Difficulty {
+        match guesses {
+            0 => Difficulty::Easy,
+            1..=10 => Difficulty::Medium,
+            11..=100 => Difficulty::Hard,
+            _ => Difficulty::Evil,
+        }
+    }
+
+    /// Solves the board one cell at a time, the way a person would, and
+    /// explains how each value was found. When no single is left, the value
+    /// the backtracking solver settled on is filled in as a guess. Only the
+    /// constants are taken into account, and an unsolvable board has no
+    /// steps.
+    pub fn solve_steps(&self) -> Vec<Step> {
+        match self.solve() {
+            Some(solution) => self.steps_from(&solution),
+            None => Vec::new(),
+        }
+    }
+
+    /// Like `solve_steps`, with the guesses taken from a solution already
+    /// worked out.
+    pub fn steps_from(&self, solution: &Board) -> Vec<Step> {
+        let mut board = self.clear_variables();
+        let mut steps = Vec::new();
+        loop {
+            let step = match board.next_step() {
+                Some(step) => step,
+                None => match board.next_cell() {
+                    Some((x, y)) => {
+                        let value = solution.get(x, y).value().expect("The solution is full");
+                        Step {
+                            technique: Technique::Guess,
+                            x,
+                            y,
+                            value,
+                            reason: format!(
+                                "guess at row {}, column {}: no single is left, the solver picks {}",
+                                y + 1,
+                                x + 1,
+                                value_to_char(value)
+                            ),
+                        }
+                    }
+                    None => break,
+                },
+            };
+            board.set_mut(step.x, step.y, Cell::Variable(step.value));
+            steps.push(step);
+        }
+        steps
+    }
+
+    // The first naked single on the board, or failing that the first hidden
+    // single.
+    fn next_step(&self) -> Option<Step> {
+        let n = self.n;
+        let candidates: Vec<Vec<u8>> = (0..n * n).map(|i| self.candidates(i % n, i / n)).collect();
+        for (i, values) in candidates.iter().enumerate() {
+            if values.len() == 1 {
+                let (x, y, value) = (i % n, i / n, values[0]);
+                return Some(Step {
+                    technique: Technique::NakedSingle,
+                    x,
+                    y,
+                    value,
+                    reason: format!(
+                        "naked single at row {}, column {}: only {} fits",
+                        y + 1,
+                        x + 1,
+                        value_to_char(value)
+                    ),
+                });
+            }
+        }
+        self.hidden_single(&candidates)
+            .map(|(x, y, value, unit)| Step {
+                technique: Technique::HiddenSingle,
+                x,
+                y,
+                value,
+                reason: format!(
+                    "hidden single at row {}, column {}: the only place for {} in its {}",
+                    y + 1,
+                    x + 1,
+                    value_to_char(value),
+                    unit
+                ),
+            })
+    }
+
+    // The first value that fits in just one cell of some unit, with that cell
+    // and the name of the unit. `candidates` holds the candidates of every
+    // cell, row by row.
+    fn hidden_single(&self, candidates: &[Vec<u8>]) -> Option<(usize, usize, u8, &'static str)> {
+        let n = self.n;
+        for (unit, cells) in self.units() {
+            for value in 1..=n as u8 {
+                let mut places = cells
+                    .iter()
+                    .filter(|&&(x, y)| candidates[y * n + x].contains(&value));
+                if let (Some(&(x, y)), None) = (places.next(), places.next()) {
+                    return Some((x, y, value, unit));
+                }
+            }
+        }
+        None
+    }
+
+    /// Fills in hidden singles, values that fit in just one cell of a row,
+    /// column, box or diagonal, until there are none left. None if there
+    /// weren't any to begin with.
+    pub fn apply_hidden_singles(&self) -> Option<Board> {
+        let n = self.n;
+        let mut board = self.clone();
+        let mut progress = false;
+        loop {
+            let candidates: Vec<Vec<u8>> =
+                (0..n * n).map(|i| board.candidates(i % n, i / n)).collect();
+            match board.hidden_single(&candidates) {
+                Some((x, y, v, _)) => {
+                    board.set_mut(x, y, Cell::Variable(v));
+                    progress = true;
+                }
+                None => break,
+            }
+        }
+        if progress {
+            Some(board)
+        } else {
+            None
+        }
+    }
+
+    // Every row, column, box and diagonal of the board, with the name of
+    // the kind of unit it is.
+    fn units(&self) -> Vec<(&'static str, Vec<(usize, usize)>)> {
+        let n = self.n;
+        let mut units = Vec::new();
+        for y in 0..n {
+            units.push(("row", (0..n).map(|x| (x, y)).collect()));
+        }
+        for x in 0..n {
+            units.push(("column", (0..n).map(|y| (x, y)).collect()));
+        }
+        for b in 0..n {
+            let (x, y) = (
+                b % (n / self.box_w) * self.box_w,
+                b / (n / self.box_w) * self.box_h,
+            );
+            units.push(("box", (0..n).map(|i| self.box_cell(x, y, i)).collect()));
+        }
+        if self.variant == Variant::Diagonal {
+            for d in 0..2 {
+                units.push((
+                    "diagonal",
+                    (0..n).map(|i| self.diagonal_cell(d, i)).collect(),
+                ));
+            }
+        }
+        units
+    }
+
+    /// Generates a puzzle with a unique solution and `clues` constants. If
+    /// no more cells can be emptied without making the solution ambiguous,
+    /// the puzzle is left with more clues than asked for.
+    pub fn generate(n: usize, clues: usize, rng: &mut Rng) -> Board {
+        let sqrt_n = isqrt(n);
+        Board::generate_with_boxes(n, sqrt_n, sqrt_n, clues, rng)
+    }
+
+    /// Like `generate`, but for a board whose boxes are `box_w` cells wide
+    /// and `box_h` cells tall.
+    pub fn generate_with_boxes(
+        n: usize,
+        box_w: usize,
+        box_h: usize,
+        clues: usize,
+        rng: &mut Rng,
+    ) -> Board {
+        Board::generate_bounded(n, box_w, box_h, clues, usize::MAX, rng)
+    }
+
+    /// Like `generate_with_boxes`, but a clue is only taken away if the
+    /// search can tell the puzzle stays unique within `max_nodes`, see
+    /// `unique_solution_bounded`. Otherwise it is kept, so the puzzle may
+    /// end up with more than `clues` of them.
+    pub fn generate_bounded(
+        n: usize,
+        box_w: usize,
+        box_h: usize,
+        clues: usize,
+        max_nodes: usize,
+        rng: &mut Rng,
+    ) -> Board {
+        // Fill an empty board, trying the values in a random order for every
+        // cell so we don't end up with the same grid each time.
+        let mut orders = vec![(1..=n as u8).collect::<Vec<u8>>(); n * n];
+        for order in orders.iter_mut() {
+            rng.shuffle(order);
+        }
+        let solution = Search::new(&Board::with_boxes(n, box_w, box_h), Some(orders))
+            .next_solution()
+            .expect("An empty board always has a solution");
+        let mut board = solution;
+        for cell in board.squares.iter_mut() {
+            if let Cell::Variable(v) = *cell {
+                *cell = Cell::Constant(v);
+            }
+        }
+
+        let mut cells: Vec<usize> = (0..n * n).collect();
+        rng.shuffle(&mut cells);
+        let mut remaining = n * n;
+        for i in cells {
+            if remaining <= clues {
+                break;
+            }
+            let cell = board.squares[i];
+            board.squares[i] = Cell::Empty;
+            if let Ok(Some(_)) = board.unique_solution_bounded(max_nodes) {
+                remaining -= 1;
+            } else {
+                board.squares[i] = cell;
+            }
+        }
+        board
+    }
+
+    /// Like `generate`, but the same seed always gives the same puzzle.
+    pub fn generate_seeded(n: usize, clues: usize, seed: u64) -> Board {
+        Board::generate(n, clues, &mut Rng::new(seed))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SolveError {
+    // The position of a constant that conflicts with another one.
+    InvalidInput(usize, usize),
+    Unsolvable,
+}
+
+/// The search took more steps than it was allowed, see `Board::solve_bounded`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BudgetExceeded;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SolveStats {
+    // The number of times the search visited a cell to try a value there.
+    pub nodes: usize,
+    // The number of times it ran out of values for a cell and had to go back.
+    pub backtracks: usize,
+    // The number of times it had to pick between several values for a cell.
+    pub guesses: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+    Evil,
+}
+
+/// One cell filled in by `Board::solve_steps`, with the reason it holds
+/// its value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Step {
+    pub technique: Technique,
+    pub x: usize,
+    pub y: usize,
+    pub value: u8,
+    pub reason: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Technique {
+    // The value is the only candidate left in the cell.
+    NakedSingle,
+    // The cell is the only place left for the value in one of its units.
+    HiddenSingle,
+    // Nothing simpler applies, so the solver's answer is filled in.
+    Guess,
+}
+
+/// A search that `Board::solve_step` advances one step at a time.
+pub struct SolverState {
+    search: Search,
+}
+
+impl SolverState {
+    /// Starts a search of the board. The cells that naked and hidden singles
+    /// settle are filled in right away, before the first step.
+    pub fn new(board: &Board) -> SolverState {
+        SolverState {
+            search: Search::new(board, None),
+        }
+    }
+
+    /// The board as far as the search has got.
+    pub fn board(&self) -> &Board {
+        &self.search.board
+    }
+
+    pub fn stats(&self) -> SolveStats {
+        self.search.stats
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StepResult {
+    // A value was tried in the cell.
+    Placed(usize, usize, u8),
+    // Every value of the cell failed, so it was emptied again.
+    Backtracked(usize, usize),
+    // The board holds a solution.
+    Done,
+    // There are no more solutions.
+    Failed,
+}
+
+/// A small xorshift generator, so that puzzles can be generated without
+/// depending on a source of randomness.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // The state of a xorshift generator must never be zero.
+        Rng(if seed == 0 {
+            0x9E37_79B9_7F4A_7C15
+        } else {
+            seed
+        })
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = (self.next_u64() % (i as u64 + 1)) as usize;
+            items.swap(i, j);
+        }
+    }
+}
+
+// A cell the search has visited, and the index in its order of the value to
+// try there next.
+#[derive(Clone, Copy)]
+struct Frame {
+    x: usize,
+    y: usize,
+    next_candidate: usize,
+}
+
+// A backtracking search over the solutions of a board. It keeps one bitmask
+// per row, column and box, where bit v is set when the value v is used in
+// that unit. This limits the solver to n < 32.
+struct Search {
+    board: Board,
+    rows: Vec<u32>,
+    cols: Vec<u32>,
+    boxes: Vec<u32>,
+    // Only used by the diagonal variant.
+    diagonals: [u32; 2],
+    // The cells we have filled so far, in the order we filled them. When we
+    // backtrack to one we go on from its `next_candidate`.
+    stack: Vec<Frame>,
+    // The cell to visit in the next step.
+    next: Option<Frame>,
+    // The order in which to try the values of each cell, ascending if None.
+    orders: Option<Vec<Vec<u8>>>,
+    // Whether the board holds a solution we have already returned.
+    found: bool,
+    done: bool,
+    stats: SolveStats,
+}
+
+impl Search {
+    fn new(board: &Board, orders: Option<Vec<Vec<u8>>>) -> Search {
+        // Anything that isn't a constant is up for grabs.
+        let mut board = board.clone();
+        for cell in board.squares.iter_mut() {
+            if let Cell::Variable(_) = cell {
+                *cell = Cell::Empty;
+            }
+        }
+        for notes in board.notes.iter_mut() {
+            notes.clear();
+        }
+        // Constants that conflict anywhere on the board are caught here,
+        // before any searching.
+        let done = !board.is_valid() || !board.propagate();
+
+        let n = board.n;
+        let mut search = Search {
+            rows: vec![0; n],
+            cols: vec![0; n],
+            boxes: vec![0; n],
+            diagonals: [0; 2],
+            stack: Vec::new(),
+            next: Search::frame(board.next_cell()),
+            orders,
+            found: false,
+            done,
+            stats: SolveStats::default(),
+            board,
+        };
+        for y in 0..n {
+            for x in 0..n {
+                if let Cell::Variable(v) | Cell::Constant(v) = search.board.get(x, y) {
+                    search.toggle(x, y, v);
+                }
+            }
+        }
+        search
+    }
+
+    fn box_of(&self, x: usize, y: usize) -> usize {
+        y / self.board.box_h * self.board.box_h + x / self.board.box_w
+    }
+
+    // Marks the value as used in the cell's units, or unused if it was used.
+    fn toggle(&mut self, x: usize, y: usize, v: u8) {
+        let b = self.box_of(x, y);
+        self.rows[y] ^= 1 << v;
+        self.cols[x] ^= 1 << v;
+        self.boxes[b] ^= 1 << v;
+        for d in self.board.diagonals_of(x, y) {
+            self.diagonals[d] ^= 1 << v;
+        }
+    }
+
+    // The values used in any of the cell's units.
+    fn used(&self, x: usize, y: usize) -> u32 {
+        let b = self.box_of(x, y);
+        let mut used = self.rows[y] | self.cols[x] | self.boxes[b];
+        for d in self.board.diagonals_of(x, y) {
+            used |= self.diagonals[d];
+        }
+        // Knight moves don't make units we could keep masks for, so look at
+        // the board instead.
+        for (knight_x, knight_y) in self.board.knight_cells(x, y) {
+            if let Some(v) = self.board.get(knight_x, knight_y).value() {
+                used |= 1 << v;
+            }
+        }
+        used
+    }
+
+    fn value(&self, i: usize, k: usize) -> u8 {
+        match &self.orders {
+            Some(orders) => orders[i][k],
+            None => k as u8 + 1,
+        }
+    }
+
+    // A cell visited for the first time starts from its first value.
+    fn frame(cell: Option<(usize, usize)>) -> Option<Frame> {
+        cell.map(|(x, y)| Frame {
+            x,
+            y,
+            next_candidate: 0,
+        })
+    }
+
+    fn next_solution(&mut self) -> Option<Board> {
+        loop {
+            match self.step() {
+                StepResult::Done => return Some(self.board.clone()),
+                StepResult::Failed => return None,
+                StepResult::Placed(..) | StepResult::Backtracked(..) => (),
+            }
+        }
+    }
+
+    // Like `next_solution`, but gives up once more than `max_nodes` cells
+    // have been visited since the search started.
+    fn next_solution_bounded(&mut self, max_nodes: usize) -> Result<Option<Board>, BudgetExceeded> {
+        loop {
+            match self.step() {
+                StepResult::Done => return Ok(Some(self.board.clone())),
+                StepResult::Failed => return Ok(None),
+                _ if self.stats.nodes > max_nodes => return Err(BudgetExceeded),
+                StepResult::Placed(..) | StepResult::Backtracked(..) => (),
+            }
+        }
+    }
+
+    // Tries one value in one cell, or gives up on a cell and goes back to
+    // the one filled before it.
+    fn step(&mut self) -> StepResult {
+        if self.done {
+            return StepResult::Failed;
+        }
+        if self.found {
+            // Continue from the last cell we filled, as if it were a dead end.
+            self.found = false;
+            self.next = self.stack.pop();
+            if self.next.is_none() {
+                self.done = true;
+                return StepResult::Failed;
+            }
+        }
+
+        let Frame {
+            x,
+            y,
+            next_candidate: first,
+        } = match self.next {
+            Some(frame) => frame,
+            None => {
+                self.found = true;
+                return StepResult::Done;
+            }
+        };
+        self.stats.nodes += 1;
+        let i = y * self.board.n + x;
+        // A cell we came back to still holds the value we tried last.
+        if let Cell::Variable(v) = self.board.squares[i] {
+            self.toggle(x, y, v);
+        }
+
+        let used = self.used(x, y);
+        let board = &self.board;
+        let allowed = |v: u8| used & (1 << v) == 0 && board.cages_allow(x, y, v);
+        if first == 0 && (1..=self.board.n as u8).filter(|&v| allowed(v)).count() > 1 {
+            self.stats.guesses += 1;
+        }
+        match (first..self.board.n).find(|&k| allowed(self.value(i, k))) {
+            Some(k) => {
+                let v = self.value(i, k);
+                self.board.set_mut(x, y, Cell::Variable(v));
+                self.toggle(x, y, v);
+                self.stack.push(Frame {
+                    x,
+                    y,
+                    next_candidate: k + 1,
+                });
+                self.next = Search::frame(self.board.next_cell());
+                StepResult::Placed(x, y, v)
+            }
+            None => {
+                // Backtrack.
+                self.stats.backtracks += 1;
+                self.board.set_mut(x, y, Cell::Empty);
+                self.next = self.stack.pop();
+                if self.next.is_none() {
+                    self.done = true;
+                }
+                StepResult::Backtracked(x, y)
+            }
+        }
+    }
+}
+
+impl Iterator for Search {
+    type Item = Board;
+
+    fn next(&mut self) -> Option<Board> {
+        self.next_solution()
+    }
+}
+
+impl fmt::Debug for Board {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for y in 0..self.n {
+            for x in 0..self.n {
+                match write!(f, " {:?} ", self.get(x, y)) {
+                    Err(e) => return Err(e),
+                    _ => (),
+                }
+            }
+            match write!(f, "\n") {
+                Err(e) => return Err(e),
+                _ => (),
+            }
+        }
+        write!(f, "")
+    }
+}
+
+// Draws the board as a grid, with lines between the boxes:
+//
+// +-----+-----+
+// | 1 . | . 4 |
+// ...
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let line = vec!["-".repeat(2 * self.box_w + 1); self.n / self.box_w].join("+");
+        for y in 0..self.n {
+            if y % self.box_h == 0 {
+                writeln!(f, "+{}+", line)?;
+            }
+            for x in 0..self.n {
+                if x % self.box_w == 0 {
+                    write!(f, "| ")?;
+                }
+                match self.get(x, y).value() {
+                    Some(v) => write!(f, "{} ", value_to_char(v))?,
+                    None => write!(f, ". ")?,
+                }
+            }
+            writeln!(f, "|")?;
+        }
+        write!(f, "+{}+", line)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseBoardError(pub String);
+
+impl fmt::Display for ParseBoardError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for ParseBoardError {}
+
+// Reads the line format. Whitespace and the lines drawn by `Display` are
+// skipped, so a displayed board can be read back.
+impl FromStr for Board {
+    type Err = ParseBoardError;
+
+    fn from_str(s: &str) -> Result<Board, ParseBoardError> {
+        let line: String = s
+            .chars()
+            .filter(|c| !c.is_whitespace() && !"+-|".contains(*c))
+            .collect();
+        Board::from_line(&line).map_err(ParseBoardError)
+    }
+}
+
+// Just enough of JSON to read back what `Board::to_json` writes, without
+// pulling in a dependency for it.
+#[derive(Debug, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn parse(s: &str) -> Result<Json, String> {
+        let mut chars = s.chars().peekable();
+        let json = Json::parse_value(&mut chars)?;
+        Json::skip_whitespace(&mut chars);
+        match chars.next() {
+            None => Ok(json),
+            Some(c) => Err(format!("Unexpected '{}' after the JSON value", c)),
+        }
+    }
+
+    // The value of `key` if this is an object that has it.
+    fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn skip_whitespace(chars: &mut Peekable<Chars>) {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+    }
+
+    fn expect(chars: &mut Peekable<Chars>, expected: char) -> Result<(), String> {
+        Json::skip_whitespace(chars);
+        match chars.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(format!("Expected '{}' but found '{}'", expected, c)),
+            None => Err(format!("Expected '{}' but the JSON ended", expected)),
+        }
+    }
+
+    fn parse_value(chars: &mut Peekable<Chars>) -> Result<Json, String> {
+        Json::skip_whitespace(chars);
+        match chars.peek() {
+            Some('{') => {
+                chars.next();
+                let mut members = Vec::new();
+                Json::skip_whitespace(chars);
+                if chars.peek() == Some(&'}') {
+                    chars.next();
+                    return Ok(Json::Object(members));
+                }
+                loop {
+                    Json::skip_whitespace(chars);
+                    let key = Json::parse_string(chars)?;
+                    Json::expect(chars, ':')?;
+                    members.push((key, Json::parse_value(chars)?));
+                    Json::skip_whitespace(chars);
+                    match chars.next() {
+                        Some(',') => (),
+                        Some('}') => return Ok(Json::Object(members)),
+                        _ => return Err("Expected ',' or '}' in an object".to_string()),
+                    }
+                }
+            }
+            Some('[') => {
+                chars.next();
+                let mut values = Vec::new();
+                Json::skip_whitespace(chars);
+                if chars.peek() == Some(&']') {
+                    chars.next();
+                    return Ok(Json::Array(values));
+                }
+                loop {
+                    values.push(Json::parse_value(chars)?);
+                    Json::skip_whitespace(chars);
+                    match chars.next() {
+                        Some(',') => (),
+                        Some(']') => return Ok(Json::Array(values)),
+                        _ => return Err("Expected ',' or ']' in an array".to_string()),
+                    }
+                }
+            }
+            Some('"') => Ok(Json::String(Json::parse_string(chars)?)),
+            Some(c) if c.is_ascii_alphabetic() => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !c.is_ascii_alphabetic() {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                match word.as_str() {
+                    "null" => Ok(Json::Null),
+                    "true" => Ok(Json::Bool(true)),
+                    "false" => Ok(Json::Bool(false)),
+                    _ => Err(format!("Unexpected '{}'", word)),
+                }
+            }
+            Some(_) => {
+                let mut number = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_ascii_digit()
+                        || c == '-'
+                        || c == '+'
+                        || c == '.'
+                        || c == 'e'
+                        || c == 'E')
+                    {
+                        break;
+                    }
+                    number.push(c);
+                    chars.next();
+                }
+                number
+                    .parse()
+                    .map(Json::Number)
+                    .map_err(|_| format!("Invalid number '{}'", number))
+            }
+            None => Err("Expected a value but the JSON ended".to_string()),
+        }
+    }
+
+    fn parse_string(chars: &mut Peekable<Chars>) -> Result<String, String> {
+        Json::expect(chars, '"')?;
+        let mut s = String::new();
+        loop {
+            match chars.next() {
+                Some('"') => return Ok(s),
+                Some('\\') => {
+                    let c = match chars.next() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('u') => {
+                            let hex: String = chars.by_ref().take(4).collect();
+                            u32::from_str_radix(&hex, 16)
+                                .ok()
+                                .and_then(std::char::from_u32)
+                                .ok_or_else(|| format!("Invalid escape '\\u{}'", hex))?
+                        }
+                        Some(c @ '"') | Some(c @ '\\') | Some(c @ '/') => c,
+                        _ => return Err("Invalid escape in a string".to_string()),
+                    };
+                    s.push(c);
+                }
+                Some(c) => s.push(c),
+                None => return Err("Unterminated string".to_string()),
+            }
+        }
+    }
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn board_from_str(s: &str) -> Board {
+        Board::from_str_puzzle(s).unwrap()
+    }
+
+    // Fills the empty cells of `puzzle` with `solution` as the solver would.
+    fn solution_from_str(puzzle: &str, solution: &str) -> Board {
+        let squares: Vec<Cell> = puzzle
+            .chars()
+            .zip(solution.chars())
+            .map(|(p, s)| match p.to_digit(10) {
+                Some(v) if v > 0 => Cell::Constant(v as u8),
+                _ => Cell::Variable(s.to_digit(10).unwrap() as u8),
+            })
+            .collect();
+        Board::from(&squares)
+    }
+
+    const PUZZLE_9X9: &str =
+        "53..7....6..195....98....6.8...6...34..8.3..17...2...6.6....28....419..5....8..79";
+    const SOLUTION_9X9: &str =
+        "534678912672195348198342567859761423426853791713924856961537284287419635345286179";
+
+    #[test]
+    fn test_solve_valid() {
+        let squares = [
+            Cell::Constant(2),
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Constant(4),
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Constant(2),
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+        ];
+        let correct_squares = [
+            Cell::Constant(2),
+            Cell::Variable(1),
+            Cell::Variable(3),
+            Cell::Variable(4),
+            Cell::Constant(4),
+            Cell::Variable(3),
+            Cell::Variable(1),
+            Cell::Variable(2),
+            Cell::Variable(1),
+            Cell::Variable(4),
+            Cell::Constant(2),
+            Cell::Variable(3),
+            Cell::Variable(3),
+            Cell::Variable(2),
+            Cell::Variable(4),
+            Cell::Variable(1),
+        ];
+        let board = Board::from(&squares);
+        let correct_board = Board::from(&correct_squares);
+        let solution = board.solve();
+        assert_eq!(solution.unwrap(), correct_board);
+    }
+
+    #[test]
+    fn test_solve_invalid_return_none() {
+        let squares = [
+            Cell::Constant(2),
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Constant(1),
+            Cell::Constant(4),
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Constant(2),
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Constant(1),
+        ];
+        let board = Board::from(&squares);
+        let solution = board.solve();
+        assert_eq!(solution, None);
+    }
+
+    #[test]
+    fn test_solve_checked() {
+        let board = board_from_str(PUZZLE_9X9);
+        assert_eq!(
+            board.solve_checked(),
+            Ok(solution_from_str(PUZZLE_9X9, SOLUTION_9X9))
+        );
+
+        // (0, 0) has no candidates left, but no constants conflict.
+        let board = Board::from_line(".234....1.......").unwrap();
+        assert_eq!(board.solve_checked(), Err(SolveError::Unsolvable));
+
+        // A second 5 in the first row.
+        let board = board_from_str(PUZZLE_9X9).set(1, 0, Cell::Constant(5));
+        assert_eq!(board.solve_checked(), Err(SolveError::InvalidInput(0, 0)));
+
+        // A wrong entry repeating a given doesn't stop the solver, which
+        // throws it away.
+        let board = board_from_str(PUZZLE_9X9).set(2, 0, Cell::Variable(5));
+        let solution = solution_from_str(PUZZLE_9X9, SOLUTION_9X9);
+        assert_eq!(board.solve_checked(), Ok(solution.clone()));
+        assert_eq!(board.solve(), Some(solution));
+        assert!(board.hint_at(Some((1, 0))).is_some());
+        assert!(!board.solve_steps().is_empty());
+    }
+
+    #[test]
+    fn test_count_solutions_none() {
+        let squares = [
+            Cell::Constant(2),
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Constant(1),
+            Cell::Constant(4),
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Constant(2),
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Constant(1),
+        ];
+        let board = Board::from(&squares);
+        assert_eq!(board.count_solutions(2), 0);
+    }
+
+    #[test]
+    fn test_count_solutions_unique() {
+        let squares = [
+            Cell::Empty,
+            Cell::Constant(1),
+            Cell::Constant(3),
+            Cell::Constant(4),
+            Cell::Empty,
+            Cell::Constant(3),
+            Cell::Constant(1),
+            Cell::Constant(2),
+            Cell::Empty,
+            Cell::Constant(4),
+            Cell::Constant(2),
+            Cell::Constant(3),
+            Cell::Empty,
+            Cell::Constant(2),
+            Cell::Constant(4),
+            Cell::Constant(1),
+        ];
+        let board = Board::from(&squares);
+        assert_eq!(board.count_solutions(2), 1);
+    }
+
+    #[test]
+    fn test_count_solutions_multiple() {
+        let squares = [
+            Cell::Constant(2),
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Constant(4),
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Constant(2),
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+        ];
+        let board = Board::from(&squares);
+        assert_eq!(board.count_solutions(2), 2);
+        assert_eq!(board.count_solutions(1), 1);
+    }
+
+    #[test]
+    fn test_count_solutions_two_leaves_board_unchanged() {
+        let squares = [
+            Cell::Constant(2),
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Constant(4),
+            Cell::Constant(4),
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Constant(2),
+            Cell::Constant(1),
+            Cell::Constant(4),
+            Cell::Constant(2),
+            Cell::Constant(3),
+            Cell::Constant(3),
+            Cell::Constant(2),
+            Cell::Constant(4),
+            Cell::Constant(1),
+        ];
+        let board = Board::from(&squares);
+        assert_eq!(board.count_solutions(2), 2);
+        assert_eq!(board.count_solutions(10), 2);
+        assert_eq!(board, Board::from(&squares));
+    }
+
+    #[test]
+    fn test_is_valid_clean_board() {
+        let squares = [
+            Cell::Constant(2),
+            Cell::Variable(1),
+            Cell::Variable(3),
+            Cell::Variable(4),
+            Cell::Constant(4),
+            Cell::Variable(3),
+            Cell::Variable(1),
+            Cell::Variable(2),
+            Cell::Variable(1),
+            Cell::Variable(4),
+            Cell::Constant(2),
+            Cell::Variable(3),
+            Cell::Variable(3),
+            Cell::Variable(2),
+            Cell::Variable(4),
+            Cell::Variable(1),
+        ];
+        let board = Board::from(&squares);
+        assert!(board.is_valid());
+    }
+
+    #[test]
+    fn test_is_valid_row_conflict() {
+        let squares = [
+            Cell::Constant(2),
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Constant(2),
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+        ];
+        let board = Board::from(&squares);
+        assert!(!board.is_valid());
+    }
+
+    #[test]
+    fn test_is_valid_empty_board() {
+        assert!(Board::new(4).is_valid());
+        assert!(Board::new(9).is_valid());
+    }
+
+    #[test]
+    fn test_is_complete() {
+        let solved = solution_from_str(PUZZLE_9X9, SOLUTION_9X9);
+        assert!(solved.is_solved());
+        assert!(!board_from_str(PUZZLE_9X9).is_solved());
+        // Full, but with two 3s in the first row.
+        let wrong = solved.set(0, 0, Cell::Variable(3));
+        assert!(!wrong.squares.contains(&Cell::Empty));
+        assert!(!wrong.is_solved());
+    }
+
+    #[test]
+    fn test_find_conflict() {
+        let squares = [
+            Cell::Empty,
+            Cell::Constant(3),
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Constant(3),
+            Cell::Empty,
+            Cell::Empty,
+        ];
+        let board = Board::from(&squares);
+        assert_eq!(board.find_conflict(), Some((1, 0)));
+        assert_eq!(Board::new(4).find_conflict(), None);
+    }
+
+    #[test]
+    fn test_conflicts_row_duplicate() {
+        let squares = [
+            Cell::Constant(1),
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Constant(1),
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+        ];
+        let board = Board::from(&squares);
+        assert_eq!(board.conflicts(), vec![(0, 0), (3, 0)]);
+    }
+
+    #[test]
+    fn test_conflicts_column_and_box() {
+        let squares = [
+            Cell::Constant(4),
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Constant(4),
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Empty,
+            Cell::Constant(4),
+            Cell::Empty,
+            Cell::Empty,
+        ];
+        let board = Board::from(&squares);
+        // (1, 1) shares a box with (0, 0) and a column with (1, 3).
+        assert_eq!(board.conflicts(), vec![(0, 0), (1, 1), (1, 3)]);
+    }
+
+    #[test]
+    fn test_solve_9x9() {
+        let board = board_from_str(PUZZLE_9X9);
+        let solution = board.solve().unwrap();
+        assert!(solution.is_valid());
+        assert_eq!(solution, solution_from_str(PUZZLE_9X9, SOLUTION_9X9));
+    }
+
+    #[test]
+    fn test_propagate_solves_9x9() {
+        let mut board = board_from_str(PUZZLE_9X9);
+        assert!(board.propagate());
+        assert_eq!(board, solution_from_str(PUZZLE_9X9, SOLUTION_9X9));
+    }
+
+    #[test]
+    fn test_solve_mostly_empty_9x9() {
+        let board = board_from_str(
+            "1...............................5...............................................9",
+        );
+        let solution = board.solve().unwrap();
+        assert!(solution.is_valid());
+        assert!(solution.squares.iter().all(|&cell| cell != Cell::Empty));
+        assert_eq!(solution.get(0, 0), Cell::Constant(1));
+        assert_eq!(solution.get(5, 3), Cell::Constant(5));
+        assert_eq!(solution.get(8, 8), Cell::Constant(9));
+    }
+
+    #[test]
+    fn test_solve_hard_9x9() {
+        let puzzle =
+            "8..........36......7..9.2...5...7.......457.....1...3...1....68..85...1..9....4..";
+        let solution =
+            "812753649943682175675491283154237896369845721287169534521974368438526917796318452";
+        let board = board_from_str(puzzle);
+        assert_eq!(board.solve().unwrap(), solution_from_str(puzzle, solution));
+    }
+
+    #[test]
+    fn test_cell_is_valid() {
+        let solution = solution_from_str(PUZZLE_9X9, SOLUTION_9X9);
+        assert!(solution.is_valid());
+        assert!(solution.cell_is_valid(4, 4));
+
+        // A duplicate 5 in the first row.
+        let board = board_from_str(&format!("{:.<81}", "53..7...5"));
+        assert!(!board.is_valid());
+        assert!(!board.cell_is_valid(0, 0));
+        assert!(!board.cell_is_valid(8, 0));
+        assert!(board.cell_is_valid(1, 0));
+
+        // A duplicate 6 in the top left box, in different rows and columns.
+        let board = board_from_str(&format!("{:.<81}", "6.........6"));
+        assert!(!board.is_valid());
+        assert!(!board.cell_is_valid(0, 0));
+        assert!(!board.cell_is_valid(1, 1));
+    }
+
+    #[test]
+    fn test_propagate_near_complete() {
+        let puzzle =
+            "5.4678912672..5348198342.67859761423426853791713924856961537284287419635345286.79";
+        let mut board = board_from_str(puzzle);
+        assert!(board.propagate());
+        assert_eq!(board, solution_from_str(puzzle, SOLUTION_9X9));
+    }
+
+    #[test]
+    fn test_propagate_contradiction() {
+        // The last cell of the first row can only be a 4, which its column
+        // already has.
+        let mut board = board_from_str(&format!("{:.<16}", "123....4"));
+        assert!(!board.propagate());
+    }
+
+    #[test]
+    fn test_from_str_puzzle() {
+        let board = Board::from_str_puzzle(&PUZZLE_9X9.replace(".", "0")).unwrap();
+        assert_eq!(board.n, 9);
+        assert_eq!(board.get(0, 0), Cell::Constant(5));
+        assert_eq!(board.get(2, 0), Cell::Empty);
+        assert_eq!(board, Board::from_str_puzzle(PUZZLE_9X9).unwrap());
+    }
+
+    #[test]
+    fn test_from_str_puzzle_invalid() {
+        assert!(Board::from_str_puzzle(&PUZZLE_9X9[1..]).is_err());
+        assert!(Board::from_str_puzzle("1234.....5......").is_err());
+        assert!(Board::from_str_puzzle("1234....x.......").is_err());
+    }
+
+    #[test]
+    fn test_puzzle_string_round_trip() {
+        let empty = "0000000000000000";
+        for puzzle in [PUZZLE_9X9, SOLUTION_9X9, "2...4.....2.....", empty].iter() {
+            let board = Board::from_str_puzzle(puzzle).unwrap();
+            let line = board.to_string_puzzle();
+            assert_eq!(line, puzzle.replace(".", "0"));
+            assert_eq!(Board::from_str_puzzle(&line).unwrap(), board);
+        }
+        let solved = board_from_str(PUZZLE_9X9).solve().unwrap();
+        assert_eq!(solved.to_string_puzzle(), SOLUTION_9X9);
+    }
+
+    #[test]
+    fn test_candidates() {
+        let board = board_from_str(PUZZLE_9X9);
+        assert_eq!(board.candidates(2, 0), vec![1, 2, 4]);
+        assert_eq!(board.candidates(4, 4), vec![5]);
+        assert_eq!(board.candidates(0, 0), Vec::<u8>::new());
+        assert_eq!(Board::new(4).candidates(1, 1), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_candidates_match_constraints() {
+        // A value is a candidate exactly when placing it breaks no constraint.
+        let board = board_from_str(PUZZLE_9X9);
+        for y in 0..9 {
+            for x in 0..9 {
+                if board.get(x, y) != Cell::Empty {
+                    continue;
+                }
+                let candidates = board.candidates(x, y);
+                for v in 1..=9 {
+                    let placed = board.set(x, y, Cell::Variable(v));
+                    assert_eq!(candidates.contains(&v), placed.cell_is_valid(x, y));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_generate_unique() {
+        for seed in 1..10 {
+            let board = Board::generate(4, 4, &mut Rng::new(seed));
+            assert_eq!(board.count_solutions(2), 1);
+            assert!(board.squares.iter().all(|&cell| match cell {
+                Cell::Constant(_) | Cell::Empty => true,
+                Cell::Variable(_) => false,
+            }));
+        }
+        let board = Board::generate(9, 30, &mut Rng::new(42));
+        assert_eq!(board.count_solutions(2), 1);
+    }
+
+    #[test]
+    fn test_generate_givens() {
+        for &(n, givens) in [(4, 6), (9, 30)].iter() {
+            for seed in 1..4 {
+                let board = Board::generate(n, givens, &mut Rng::new(seed));
+                let constants = board
+                    .squares
+                    .iter()
+                    .filter(|&&cell| cell != Cell::Empty)
+                    .count();
+                assert_eq!(constants, givens);
+                assert!(board.solve().is_some());
+            }
+        }
+    }
+
+    #[test]
+    fn test_generate_seeded() {
+        let board = Board::generate_seeded(9, 30, 7);
+        assert_eq!(board, Board::generate_seeded(9, 30, 7));
+        assert!(board != Board::generate_seeded(9, 30, 8));
+    }
+
+    #[test]
+    fn test_line_round_trip() {
+        let board = board_from_str(PUZZLE_9X9);
+        assert_eq!(board.to_line(), PUZZLE_9X9);
+        assert_eq!(Board::from_line(&board.to_line()).unwrap(), board);
+
+        let mut board = Board::new(16);
+        board.squares[0] = Cell::Constant(16);
+        board.squares[17] = Cell::Constant(10);
+        board.squares[255] = Cell::Constant(9);
+        let line = board.to_line();
+        assert_eq!(&line[..18], "G................A");
+        assert_eq!(Board::from_line(&line).unwrap(), board);
+    }
+
+    #[test]
+    fn test_from_line() {
+        let board = Board::from_line(&PUZZLE_9X9.replace(".", "0")).unwrap();
+        assert_eq!(board, board_from_str(PUZZLE_9X9));
+        assert!(Board::from_line(&PUZZLE_9X9[..80]).is_err());
+        assert!(Board::from_line(&PUZZLE_9X9.replace("5", "x")).is_err());
+        assert!(Board::from_line(&PUZZLE_9X9.replace("5", "A")).is_err());
+    }
+
+    #[test]
+    fn test_checked_access() {
+        let board = board_from_str(PUZZLE_9X9);
+        assert_eq!(board.get_checked(0, 0), Some(Cell::Constant(5)));
+        assert_eq!(board.get_checked(8, 8), Some(Cell::Constant(9)));
+        assert_eq!(board.get_checked(9, 0), None);
+        assert_eq!(board.get_checked(0, 9), None);
+
+        let set = board.set_checked(2, 0, Cell::Variable(4)).unwrap();
+        assert_eq!(set.get(2, 0), Cell::Variable(4));
+        assert_eq!(board.set_checked(9, 0, Cell::Variable(4)), None);
+        assert_eq!(board.set_checked(0, 9, Cell::Variable(4)), None);
+    }
+
+    #[test]
+    fn test_display() {
+        let board: Board = "1..4..3..3..4..1".parse().unwrap();
+        let grid = "\
++-----+-----+
+| 1 . | . 4 |
+| . . | 3 . |
++-----+-----+
+| . 3 | . . |
+| 4 . | . 1 |
++-----+-----+";
+        assert_eq!(board.to_string(), grid);
+        assert_eq!(grid.parse::<Board>().unwrap(), board);
+
+        let board = board_from_str(PUZZLE_9X9);
+        let displayed = board.to_string();
+        assert!(displayed.starts_with("+-------+-------+-------+\n| 5 3 . | . 7 . | . . . |"));
+        assert_eq!(displayed.lines().filter(|l| l.starts_with('+')).count(), 4);
+        assert_eq!(displayed.parse::<Board>().unwrap(), board);
+
+        let board = Board::with_boxes(6, 3, 2);
+        assert!(board.to_string().starts_with("+-------+-------+\n"));
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!(PUZZLE_9X9.parse::<Board>().unwrap(), board_from_str(PUZZLE_9X9));
+        let error = "1..2".parse::<Board>().unwrap_err();
+        assert_eq!(error.to_string(), "A 2x2 board can't have square boxes");
+        assert!("1..4x.3..3..4..1".parse::<Board>().is_err());
+    }
+
+    #[test]
+    fn test_parse_value() {
+        let board = Board::new(16);
+        assert_eq!(board.parse_value("7"), Some(7));
+        assert_eq!(board.parse_value("A"), Some(10));
+        assert_eq!(board.parse_value("g"), Some(16));
+        assert_eq!(board.parse_value("16"), Some(16));
+        assert_eq!(board.parse_value("H"), None);
+        assert_eq!(board.parse_value("0"), None);
+        assert_eq!(board.parse_value("AB"), None);
+        assert_eq!(board.parse_value(""), None);
+
+        let board = Board::new(9);
+        assert_eq!(board.parse_value("9"), Some(9));
+        assert_eq!(board.parse_value("A"), None);
+        assert_eq!(board.parse_value("10"), None);
+    }
+
+    #[test]
+    fn test_toggle_note() {
+        let board = Board::new(4).toggle_note(1, 2, 3).toggle_note(1, 2, 1);
+        assert_eq!(board.get_notes(1, 2), vec![1, 3]);
+        assert_eq!(board.toggle_note(1, 2, 3).get_notes(1, 2), vec![1]);
+
+        // Filling the cell removes its notes, and filled cells can't get any.
+        let board = board.set(1, 2, Cell::Constant(2));
+        assert_eq!(board.get_notes(1, 2), Vec::<u8>::new());
+        assert_eq!(board.toggle_note(1, 2, 4).get_notes(1, 2), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn test_solve_ignores_notes() {
+        let board = board_from_str(PUZZLE_9X9).toggle_note(2, 0, 9);
+        let solution = board.solve().unwrap();
+        assert_eq!(solution, solution_from_str(PUZZLE_9X9, SOLUTION_9X9));
+    }
+
+    #[test]
+    fn test_try_from() {
+        let board = Board::try_from(&[Cell::Empty; 16]).unwrap();
+        assert_eq!(board, Board::new(4));
+        assert!(Board::try_from(&[Cell::Empty; 15]).is_err());
+        assert!(Board::try_from(&[Cell::Constant(1); 2]).is_err());
+
+        // Square, but 2x2 and 5x5 boards can't be divided into square boxes.
+        assert!(Board::try_from(&[Cell::Empty; 4]).is_err());
+        assert!(Board::try_from(&[Cell::Empty; 25]).is_err());
+        assert!(Board::from_line(&".".repeat(25)).is_err());
+        assert!(Board::from_str_puzzle(&"0".repeat(25)).is_err());
+
+        // Values have to be between 1 and n.
+        let mut squares = [Cell::Empty; 16];
+        squares[3] = Cell::Variable(4);
+        assert!(Board::try_from(&squares).is_ok());
+        squares[3] = Cell::Constant(0);
+        assert!(Board::try_from(&squares).is_err());
+        squares[3] = Cell::Variable(5);
+        assert!(Board::try_from(&squares).is_err());
+        squares[3] = Cell::Constant(32);
+        assert!(Board::try_from(&squares).is_err());
+    }
+
+    #[test]
+    fn test_hint() {
+        let board = board_from_str(PUZZLE_9X9);
+        assert_eq!(board.hint(), Some((2, 0, 4)));
+        let board = board.set(2, 0, Cell::Variable(4)).set(3, 0, Cell::Variable(6));
+        assert_eq!(board.hint(), Some((5, 0, 8)));
+        assert_eq!(solution_from_str(PUZZLE_9X9, SOLUTION_9X9).hint(), None);
+    }
+
+    #[test]
+    fn test_cell_value() {
+        assert_eq!(Cell::Constant(3).value(), Some(3));
+        assert_eq!(Cell::Variable(7).value(), Some(7));
+        assert_eq!(Cell::Empty.value(), None);
+        assert!(Cell::Constant(3).is_constant());
+        assert!(!Cell::Variable(3).is_constant());
+        assert!(!Cell::Empty.is_constant());
+    }
+
+    #[test]
+    fn test_is_peer() {
+        let board = Board::new(9);
+        assert!(board.is_peer((4, 4), (4, 0)));
+        assert!(board.is_peer((4, 4), (0, 4)));
+        assert!(board.is_peer((4, 4), (3, 5)));
+        assert!(!board.is_peer((4, 4), (2, 2)));
+
+        // Boxes 2 cells wide and 3 cells tall.
+        let board = Board::with_boxes(6, 2, 3);
+        assert!(board.is_peer((0, 0), (1, 2)));
+        assert!(!board.is_peer((0, 0), (2, 1)));
+    }
+
+    #[test]
+    fn test_diagonal_variant() {
+        let mut solved = solution_from_str(PUZZLE_9X9, SOLUTION_9X9);
+        assert!(solved.is_valid());
+        // The main diagonal of this solution has two 7s.
+        solved.variant = Variant::Diagonal;
+        assert!(!solved.is_valid());
+        assert!(!solved.cell_is_valid(1, 1));
+        assert!(solved.cell_is_valid(1, 0));
+
+        let mut board = Board::new(9).set(0, 0, Cell::Constant(1));
+        assert!(board.candidates(8, 8).contains(&1));
+        board.variant = Variant::Diagonal;
+        assert!(!board.candidates(8, 8).contains(&1));
+        // (7, 1) is on the other diagonal.
+        assert!(board.candidates(7, 1).contains(&1));
+
+        let solution = board.solve().unwrap();
+        assert!(solution.is_solved());
+        for i in 0..9 {
+            assert!(solution.cell_is_valid(i, i));
+            assert!(solution.cell_is_valid(8 - i, i));
+        }
+    }
+
+    #[test]
+    fn test_empty_count() {
+        assert_eq!(Board::new(9).empty_count(), 81);
+        assert_eq!(board_from_str(PUZZLE_9X9).empty_count(), 51);
+        assert_eq!(solution_from_str(PUZZLE_9X9, SOLUTION_9X9).empty_count(), 0);
+    }
+
     #[test]
-    fn test_solve_valid() {
-        let squares = [
-            Cell::Constant(2),
-            Cell::Empty,
-            Cell::Empty,
-            Cell::Empty,
-            Cell::Constant(4),
-            Cell::Empty,
-            Cell::Empty,
-            Cell::Empty,
-            Cell::Empty,
-            Cell::Empty,
-            Cell::Constant(2),
-            Cell::Empty,
-            Cell::Empty,
-            Cell::Empty,
-            Cell::Empty,
-            Cell::Empty,
-        ];
-        let correct_squares = [
-            Cell::Constant(2),
-            Cell::Variable(1),
-            Cell::Variable(3),
-            Cell::Variable(4),
-            Cell::Constant(4),
-            Cell::Variable(3),
-            Cell::Variable(1),
-            Cell::Variable(2),
-            Cell::Variable(1),
-            Cell::Variable(4),
-            Cell::Constant(2),
-            Cell::Variable(3),
-            Cell::Variable(3),
-            Cell::Variable(2),
-            Cell::Variable(4),
-            Cell::Variable(1),
+    fn test_clear() {
+        let board = board_from_str(PUZZLE_9X9);
+        assert_eq!(board.clear(), Board::new(9));
+
+        let mut board = Board::with_boxes(6, 2, 3).set(0, 0, Cell::Constant(1));
+        board.variant = Variant::Diagonal;
+        let cleared = board.clear();
+        assert_eq!(cleared.get(0, 0), Cell::Empty);
+        assert_eq!((cleared.box_w, cleared.box_h), (2, 3));
+        assert_eq!(cleared.variant, Variant::Diagonal);
+    }
+
+    #[test]
+    fn test_clear_variables() {
+        let puzzle = board_from_str(PUZZLE_9X9);
+        let board = solution_from_str(PUZZLE_9X9, SOLUTION_9X9)
+            .set(2, 0, Cell::Empty)
+            .toggle_note(2, 0, 4);
+        assert_eq!(board.clear_variables(), puzzle);
+        assert_eq!(puzzle.clear_variables(), puzzle);
+
+        // Constants, variables and empty cells mixed in one row.
+        let board = Board::new(4)
+            .set(0, 0, Cell::Constant(1))
+            .set(1, 0, Cell::Variable(2))
+            .set(3, 0, Cell::Variable(4))
+            .clear_variables();
+        assert_eq!(board, Board::new(4).set(0, 0, Cell::Constant(1)));
+    }
+
+    #[test]
+    fn test_hint_at() {
+        let board = board_from_str(PUZZLE_9X9);
+        assert_eq!(board.hint_at(Some((0, 8))), Some((0, 8, 3)));
+        // A filled cell can't be revealed, so fall back to the first empty one.
+        assert_eq!(board.hint_at(Some((0, 0))), Some((2, 0, 4)));
+        assert_eq!(board.hint_at(None), board.hint());
+    }
+
+    #[test]
+    fn test_solve_6x6_with_boxes() {
+        // Boxes are 2 cells wide and 3 cells tall.
+        let puzzle = ".2.3....6.1....2.5.1......3..4.5....";
+        let solution = "125346536412641235412563263154354621";
+        let mut board = Board::with_boxes(6, 2, 3);
+        for (i, c) in puzzle.chars().enumerate() {
+            if let Some(v) = c.to_digit(10) {
+                board.squares[i] = Cell::Constant(v as u8);
+            }
+        }
+        assert_eq!(board.count_solutions(2), 1);
+        assert_eq!(board.solve().unwrap().to_line(), solution);
+
+        // (0, 0) and (1, 2) share a box.
+        let board = Board::with_boxes(6, 2, 3)
+            .set(0, 0, Cell::Constant(1))
+            .set(1, 2, Cell::Constant(1));
+        assert_eq!(board.conflicts(), vec![(0, 0), (1, 2)]);
+    }
+
+    #[test]
+    fn test_set_mut() {
+        let original = board_from_str(PUZZLE_9X9);
+        let mut board = original.clone();
+        board.set_mut(2, 0, Cell::Variable(4));
+        let changed: Vec<usize> = (0..81)
+            .filter(|&i| board.squares[i] != original.squares[i])
+            .collect();
+        assert_eq!(changed, vec![2]);
+        assert_eq!(board, original.set(2, 0, Cell::Variable(4)));
+    }
+
+    #[test]
+    fn test_try_new() {
+        for &n in [1, 4, 9, 16, 25].iter() {
+            assert_eq!(Board::try_new(n).unwrap().squares.len(), n * n);
+        }
+        for &n in [0, 5, 6, 36].iter() {
+            assert!(Board::try_new(n).is_err());
+        }
+    }
+
+    #[test]
+    fn test_solutions() {
+        // There are 288 ways to fill an empty 4x4 board.
+        let solutions: Vec<Board> = Board::new(4).solutions().collect();
+        assert_eq!(solutions.len(), 288);
+        assert!(solutions.iter().all(|board| board.is_solved()));
+        for (i, board) in solutions.iter().enumerate() {
+            assert!(!solutions[..i].contains(board));
+        }
+
+        let board = Board::new(4)
+            .set(0, 0, Cell::Constant(1))
+            .set(1, 0, Cell::Constant(2))
+            .set(0, 1, Cell::Constant(3));
+        assert_eq!(board.solutions().count(), 12);
+        assert_eq!(board.solutions().take(3).count(), 3);
+        assert_eq!(board_from_str(PUZZLE_9X9).solutions().count(), 1);
+    }
+
+    #[test]
+    fn test_branches() {
+        let board = Board::new(4)
+            .set(0, 0, Cell::Constant(1))
+            .set(1, 0, Cell::Constant(2))
+            .set(0, 1, Cell::Constant(3));
+        let branches = board.branches();
+        assert!(branches.len() > 1);
+        let count: usize = branches.iter().map(|b| b.solutions().count()).sum();
+        assert_eq!(count, board.solutions().count());
+
+        let full = Board::from_line(SOLUTION_9X9).unwrap();
+        assert_eq!(full.branches().len(), 0);
+    }
+
+    #[test]
+    fn test_fill_from() {
+        let puzzle = board_from_str(PUZZLE_9X9);
+        // Lines describe every value as a constant.
+        let line = Board::from_line(SOLUTION_9X9).unwrap();
+        assert_eq!(puzzle.fill_from(&line), puzzle.solve().unwrap());
+    }
+
+    #[test]
+    fn test_solve_with_stats() {
+        let (solution, easy) = board_from_str(PUZZLE_9X9).solve_with_stats();
+        assert_eq!(solution, Some(solution_from_str(PUZZLE_9X9, SOLUTION_9X9)));
+        assert_eq!(easy.backtracks, 0);
+
+        let hard =
+            "8..........36......7..9.2...5...7.......457.....1...3...1....68..85...1..9....4..";
+        let (solution, hard) = board_from_str(hard).solve_with_stats();
+        assert!(solution.is_some());
+        assert!(hard.nodes > 100 * (easy.nodes + 1));
+        assert!(hard.backtracks > 0);
+    }
+
+    #[test]
+    fn test_difficulty() {
+        assert_eq!(board_from_str(PUZZLE_9X9).difficulty(), Difficulty::Easy);
+        // Hidden singles are enough for this one.
+        let singles =
+            "1.4...3...5..4.9..2...98......16.......8..7.4.27....36...9....1...5.....36......2";
+        assert_eq!(board_from_str(singles).difficulty(), Difficulty::Easy);
+        let medium =
+            ".189..654...4....7..721...9......2......6..459...8....5...2..766..84..9...3..5...";
+        assert_eq!(board_from_str(medium).difficulty(), Difficulty::Medium);
+        let hard =
+            ".6.......4...7..6...7..9258...2.......9..8..7..15....97....68..3...5.......3...15";
+        assert_eq!(board_from_str(hard).difficulty(), Difficulty::Hard);
+        let evil =
+            "8..........36......7..9.2...5...7.......457.....1...3...1....68..85...1..9....4..";
+        assert_eq!(board_from_str(evil).difficulty(), Difficulty::Evil);
+    }
+
+    #[test]
+    fn test_solve_steps() {
+        let board = board_from_str(PUZZLE_9X9);
+        let steps = board.solve_steps();
+        assert_eq!(
+            steps[0],
+            Step {
+                technique: Technique::NakedSingle,
+                x: 4,
+                y: 4,
+                value: 5,
+                reason: "naked single at row 5, column 5: only 5 fits".to_string(),
+            }
+        );
+        assert_eq!(steps.len(), board.empty_count());
+        assert!(steps.iter().all(|step| step.technique != Technique::Guess));
+        let mut solved = board.clone();
+        for step in steps.iter() {
+            solved.set_mut(step.x, step.y, Cell::Variable(step.value));
+        }
+        assert_eq!(solved, solution_from_str(PUZZLE_9X9, SOLUTION_9X9));
+
+        // Nothing can be deduced on an empty board, so it starts with a guess.
+        let steps = Board::new(4).solve_steps();
+        assert_eq!(steps[0].technique, Technique::Guess);
+        assert_eq!(steps.len(), 16);
+
+        let unsolvable = board.set(2, 0, Cell::Constant(1));
+        assert!(unsolvable.solve_steps().is_empty());
+    }
+
+    // Steps until the search either finds a solution or gives up.
+    fn finish(state: &mut SolverState) -> StepResult {
+        loop {
+            match Board::solve_step(state) {
+                StepResult::Placed(..) | StepResult::Backtracked(..) => (),
+                result => return result,
+            }
+        }
+    }
+
+    #[test]
+    fn test_solve_step() {
+        let puzzles = [
+            PUZZLE_9X9,
+            ".6.......4...7..6...7..9258...2.......9..8..7..15....97....68..3...5.......3...15",
         ];
-        let board = Board::from(&squares);
-        let correct_board = Board::from(&correct_squares);
-        let solution = board.solve();
-        assert_eq!(solution.unwrap(), correct_board);
+        for puzzle in puzzles.iter() {
+            let board = board_from_str(puzzle);
+            let mut state = SolverState::new(&board);
+            let mut backtracks = 0;
+            loop {
+                match Board::solve_step(&mut state) {
+                    StepResult::Placed(x, y, v) => {
+                        assert_eq!(state.board().get(x, y), Cell::Variable(v))
+                    }
+                    StepResult::Backtracked(x, y) => {
+                        assert_eq!(state.board().get(x, y), Cell::Empty);
+                        backtracks += 1;
+                    }
+                    StepResult::Done => break,
+                    StepResult::Failed => panic!("The puzzle has a solution"),
+                }
+            }
+            assert_eq!(Some(state.board().clone()), board.solve());
+            assert_eq!(state.stats(), board.solve_with_stats().1);
+            assert_eq!(backtracks, state.stats().backtracks);
+            // Stepping on looks for another solution, and the puzzles have
+            // only the one.
+            assert_eq!(finish(&mut state), StepResult::Failed);
+        }
+
+        let unsolvable = board_from_str(PUZZLE_9X9).set(2, 0, Cell::Constant(1));
+        assert_eq!(
+            finish(&mut SolverState::new(&unsolvable)),
+            StepResult::Failed
+        );
     }
 
     #[test]
-    fn test_solve_invalid_return_none() {
-        let squares = [
-            Cell::Constant(2),
-            Cell::Empty,
-            Cell::Empty,
-            Cell::Constant(1),
-            Cell::Constant(4),
-            Cell::Empty,
-            Cell::Empty,
-            Cell::Empty,
-            Cell::Empty,
-            Cell::Empty,
-            Cell::Constant(2),
-            Cell::Empty,
-            Cell::Empty,
-            Cell::Empty,
-            Cell::Empty,
-            Cell::Constant(1),
+    fn test_json_round_trip() {
+        let board = solution_from_str(PUZZLE_9X9, SOLUTION_9X9)
+            .set(0, 8, Cell::Empty)
+            .set(1, 8, Cell::Variable(1));
+        let json = board.to_json();
+        assert!(json.starts_with(r#"{"n":9,"box_w":3,"box_h":3,"variant":"classic","cells":[{"kind":"constant","value":5},"#));
+        assert_eq!(Board::from_json(&json), Ok(board));
+
+        let mut board = Board::with_boxes(6, 3, 2).set(5, 5, Cell::Constant(6));
+        board.variant = Variant::Diagonal;
+        assert_eq!(Board::from_json(&board.to_json()), Ok(board));
+
+        // Whitespace is fine, and the boxes and variant can be left out.
+        let cells = vec![r#"{"kind": "empty"}"#; 15].join(", ");
+        let json = format!(
+            r#" {{ "n" : 4, "cells" : [ {{"kind":"variable", "value":4}}, {} ] }} "#,
+            cells
+        );
+        assert_eq!(
+            Board::from_json(&json),
+            Ok(Board::new(4).set(0, 0, Cell::Variable(4)))
+        );
+
+        assert!(Board::from_json("").is_err());
+        assert!(Board::from_json(r#"{"n":1,"cells":[]}"#).is_err());
+        assert!(Board::from_json(r#"{"n":1,"cells":[{"kind":"constant","value":2}]}"#).is_err());
+        assert!(Board::from_json(r#"{"n":1,"cells":[{"kind":"empty"}]} x"#).is_err());
+        assert!(Board::from_json(r#"{"n":6,"box_w":4,"box_h":2,"cells":[]}"#).is_err());
+    }
+
+    #[test]
+    fn test_generate_with_boxes() {
+        for &(n, box_w, box_h, clues) in [(6, 3, 2, 13), (12, 4, 3, 72)].iter() {
+            let board = Board::generate_with_boxes(n, box_w, box_h, clues, &mut Rng::new(3));
+            assert_eq!((board.box_w, board.box_h), (box_w, box_h));
+            assert_eq!(board.count_solutions(2), 1);
+            assert_eq!(board.empty_count(), n * n - clues);
+            assert!(board.solve().unwrap().is_solved());
+        }
+
+        // Without the budget to check that a clue can go, it stays. With no
+        // budget at all, only the clues that singles can fill back in go.
+        let board = Board::generate_bounded(9, 3, 3, 0, 0, &mut Rng::new(3));
+        let unbounded = Board::generate_bounded(9, 3, 3, 0, usize::MAX, &mut Rng::new(3));
+        assert!(board.empty_count() < unbounded.empty_count());
+        assert_eq!(board.count_solutions(2), 1);
+        assert_eq!(unbounded, Board::generate(9, 0, &mut Rng::new(3)));
+    }
+
+    #[test]
+    fn test_from_json_invalid() {
+        let cells = vec![r#"{"kind":"empty","value":null}"#; 16].join(",");
+        let json = format!(r#"{{"n":4,"cells":[{}]}}"#, cells);
+        assert_eq!(Board::from_json(&json), Ok(Board::new(4)));
+
+        assert_eq!(
+            Board::from_json(&format!(r#"{{"cells":[{}]}}"#, cells)),
+            Err("Expected a board size from 1 to 25 in \"n\"".to_string())
+        );
+        assert_eq!(
+            Board::from_json(r#"{"n":4}"#),
+            Err("Expected 16 cells in \"cells\"".to_string())
+        );
+        assert_eq!(
+            Board::from_json(&format!(r#"{{"n":9,"cells":[{}]}}"#, cells)),
+            Err("Expected 81 cells in \"cells\"".to_string())
+        );
+        let missing_kind = json.replacen(r#""kind":"empty","#, "", 1);
+        assert_eq!(
+            Board::from_json(&missing_kind),
+            Err("Invalid cell 0".to_string())
+        );
+        assert!(Board::from_json(&json[..json.len() - 1]).is_err());
+        assert!(Board::from_json(&json.replacen(":", "", 1)).is_err());
+    }
+
+    #[test]
+    fn test_incorrect_cells() {
+        let board = board_from_str(PUZZLE_9X9);
+        assert!(board.incorrect_cells().is_empty());
+        // (2, 0) is 4 in the solution, and 1 doesn't conflict with anything.
+        let board = board
+            .set(2, 0, Cell::Variable(1))
+            .set(3, 0, Cell::Variable(6));
+        assert_eq!(board.incorrect_cells(), vec![(2, 0)]);
+        assert!(board.cell_is_valid(2, 0));
+
+        // Without a unique solution, only conflicts are certain mistakes.
+        let board = Board::new(4)
+            .set(0, 0, Cell::Variable(1))
+            .set(1, 0, Cell::Variable(2))
+            .set(3, 0, Cell::Variable(2));
+        assert_eq!(board.incorrect_cells(), vec![(1, 0), (3, 0)]);
+        assert!(board.set(3, 0, Cell::Empty).incorrect_cells().is_empty());
+    }
+
+    #[test]
+    fn test_from_url_hash() {
+        assert_eq!(Board::from_url_hash(""), Ok(None));
+        assert_eq!(Board::from_url_hash("#"), Ok(None));
+        let hash = format!("#{}", PUZZLE_9X9);
+        assert_eq!(
+            Board::from_url_hash(&hash),
+            Ok(Some(board_from_str(PUZZLE_9X9)))
+        );
+        assert_eq!(
+            Board::from_url_hash("#about"),
+            Err("A line of 5 cells is not square".to_string())
+        );
+        assert!(Board::from_url_hash("#123434122143x321").is_err());
+    }
+
+    #[test]
+    fn test_transformations() {
+        let solved = solution_from_str(PUZZLE_9X9, SOLUTION_9X9);
+        for board in [solved.transpose(), solved.rotate_90(), solved.rotate_180()].iter() {
+            assert!(board.is_solved());
+            assert_eq!(board.difficulty(), solved.difficulty());
+        }
+        // The top left corner goes to the top right, bottom left and bottom
+        // right corner.
+        assert_eq!(solved.rotate_90().get(8, 0), Cell::Constant(5));
+        assert_eq!(solved.rotate_90().get(8, 1), Cell::Constant(3));
+        assert_eq!(solved.transpose().get(0, 1), Cell::Constant(3));
+        assert_eq!(solved.transpose().get(2, 0), Cell::Variable(1));
+        assert_eq!(solved.rotate_180().get(8, 8), Cell::Constant(5));
+        let notes = board_from_str(PUZZLE_9X9).toggle_note(2, 0, 4);
+        assert_eq!(notes.rotate_90().get_notes(8, 2), vec![4]);
+        assert_eq!(solved.rotate_cell_90(0, 0), (8, 0));
+        assert_eq!(solved.rotate_cell_90(2, 0), (8, 2));
+        for y in 0..9 {
+            for x in 0..9 {
+                let (x_, y_) = solved.rotate_cell_90(x, y);
+                assert_eq!(solved.rotate_90().get(x_, y_), solved.get(x, y));
+            }
+        }
+        assert_eq!(solved.rotate_90().rotate_90(), solved.rotate_180());
+        assert_eq!(solved.rotate_180().rotate_180(), solved);
+        assert_eq!(solved.transpose().transpose(), solved);
+        assert_eq!(
+            solved.rotate_90().rotate_90().rotate_90().rotate_90(),
+            solved
+        );
+
+        // Rectangular boxes are turned as well.
+        let mut board = Board::with_boxes(6, 3, 2);
+        let solution = "123456456123231564564231312645645312";
+        for (i, c) in solution.chars().enumerate() {
+            board.squares[i] = Cell::Constant(c.to_digit(10).unwrap() as u8);
+        }
+        assert!(board.is_solved());
+        for board in [board.transpose(), board.rotate_90()].iter() {
+            assert_eq!((board.box_w, board.box_h), (2, 3));
+            assert!(board.is_solved());
+        }
+        assert!(board.rotate_180().is_solved());
+    }
+
+    #[test]
+    fn test_from_json_short_values() {
+        let board = solution_from_str(PUZZLE_9X9, SOLUTION_9X9).set(8, 8, Cell::Empty);
+        let cells: Vec<String> = board
+            .squares
+            .iter()
+            .map(|cell| match cell {
+                Cell::Constant(v) => format!(r#"{{"v":{},"kind":"constant"}}"#, v),
+                Cell::Variable(v) => format!(r#"{{"v":{},"kind":"variable"}}"#, v),
+                Cell::Empty => r#"{"kind":"empty"}"#.to_string(),
+            })
+            .collect();
+        let json = format!(r#"{{"n":9,"cells":[{}]}}"#, cells.join(","));
+        let parsed = Board::from_json(&json).unwrap();
+        assert_eq!(parsed, board);
+        assert_eq!(Board::from_json(&parsed.to_json()), Ok(board));
+    }
+
+    #[test]
+    fn test_unique_solution() {
+        let board = board_from_str(PUZZLE_9X9).set(2, 0, Cell::Variable(1));
+        assert_eq!(
+            board.unique_solution(),
+            Some(solution_from_str(PUZZLE_9X9, SOLUTION_9X9))
+        );
+        assert_eq!(Board::new(4).unique_solution(), None);
+        assert_eq!(
+            board_from_str(PUZZLE_9X9)
+                .set(2, 0, Cell::Constant(1))
+                .unique_solution(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_solving_leaves_board_unchanged() {
+        let board = board_from_str(PUZZLE_9X9)
+            .set(2, 0, Cell::Variable(1))
+            .toggle_note(3, 0, 6);
+        let original = board.clone();
+        assert!(board.solve().is_some());
+        assert!(board.solve_checked().is_ok());
+        assert!(board.hint().is_some());
+        assert!(!board.incorrect_cells().is_empty());
+        assert!(!board.solve_steps().is_empty());
+        assert!(board.unique_solution().is_some());
+        assert_eq!(board.count_solutions(2), 1);
+        assert_eq!(board, original);
+        assert_eq!(board.get(2, 0), Cell::Variable(1));
+        assert_eq!(board.get_notes(3, 0), vec![6]);
+    }
+
+    #[test]
+    fn test_conflicts_entered_value() {
+        // The 3 already in the top row is repeated by the player.
+        let board = board_from_str(PUZZLE_9X9).set(2, 0, Cell::Variable(3));
+        assert_eq!(board.conflicts(), vec![(1, 0), (2, 0)]);
+        // A 5 that repeats one in its row and in its box.
+        let board = board_from_str(PUZZLE_9X9).set(2, 1, Cell::Variable(5));
+        assert_eq!(board.conflicts(), vec![(0, 0), (2, 1), (5, 1)]);
+        assert!(board.set(2, 1, Cell::Empty).conflicts().is_empty());
+    }
+
+    #[test]
+    fn test_solve_puzzle() {
+        assert_eq!(solve_puzzle(PUZZLE_9X9), Some(SOLUTION_9X9.to_string()));
+        let zeros = PUZZLE_9X9.replace('.', "0");
+        assert_eq!(
+            solve_puzzle(&format!(" {}\n", zeros)),
+            Some(SOLUTION_9X9.to_string())
+        );
+        assert_eq!(solve_puzzle(SOLUTION_9X9), Some(SOLUTION_9X9.to_string()));
+        // Too short, not a digit, and two 5s in the top row.
+        assert_eq!(solve_puzzle(&PUZZLE_9X9[1..]), None);
+        assert_eq!(solve_puzzle(&PUZZLE_9X9.replace('7', "x")), None);
+        assert_eq!(solve_puzzle(&PUZZLE_9X9.replacen('.', "5", 1)), None);
+    }
+
+    #[test]
+    fn test_anti_knight_variant() {
+        let classic = solution_from_str(PUZZLE_9X9, SOLUTION_9X9);
+        let mut board = classic.clone();
+        board.variant = Variant::AntiKnight;
+        // The classic solution breaks the stricter rule somewhere.
+        assert!(classic.is_solved());
+        assert!(!board.is_solved());
+        assert!(!board.conflicts().is_empty());
+        assert!(board.is_peer((2, 2), (3, 4)));
+        assert!(!classic.is_peer((2, 2), (3, 4)));
+
+        let mut knight = Board::new(9).set(2, 2, Cell::Constant(1));
+        knight.variant = Variant::AntiKnight;
+        assert!(!knight.candidates(3, 4).contains(&1));
+        assert!(knight.candidates(3, 3).contains(&1));
+        let broken = knight.set(3, 4, Cell::Variable(1));
+        assert!(!broken.is_valid());
+        assert_eq!(broken.conflicts(), vec![(2, 2), (3, 4)]);
+
+        // The solver respects the rule.
+        let solution = knight.solve().unwrap();
+        assert!(solution.is_solved());
+        for y in 0..9 {
+            for x in 0..9 {
+                assert!(solution.check_knight_constraint(x, y));
+            }
+        }
+        assert_eq!(Board::from_json(&solution.to_json()), Ok(solution));
+    }
+
+    #[test]
+    fn test_box_sizes() {
+        let roots: Vec<usize> = [0, 1, 3, 4, 15, 16, 24, 25, 255, 256, 624, 625]
+            .iter()
+            .map(|&n| isqrt(n))
+            .collect();
+        assert_eq!(roots, vec![0, 1, 1, 2, 3, 4, 4, 5, 15, 16, 24, 25]);
+
+        for &(n, size) in [(16, 4), (25, 5)].iter() {
+            let board = Board::new(n);
+            assert_eq!((board.box_w, board.box_h), (size, size));
+            let line = ".".repeat(n * n);
+            assert_eq!(Board::from_line(&line).unwrap().box_w, size);
+            // The last cell of the first box and the first of the next one.
+            assert_eq!(board.box_cell(0, 0, n - 1), (size - 1, size - 1));
+            assert_eq!(board.box_cell(size, 0, 0), (size, 0));
+            let same_box = board
+                .set(0, 0, Cell::Constant(1))
+                .set(size - 1, size - 1, Cell::Constant(1));
+            assert!(!same_box.is_valid());
+            let next_box = board
+                .set(0, 0, Cell::Constant(1))
+                .set(size, size, Cell::Constant(1));
+            assert!(next_box.is_valid());
+        }
+    }
+
+    #[test]
+    fn test_is_valid_matches_conflicts() {
+        let mut rng = Rng::new(5);
+        for &variant in [Variant::Classic, Variant::Diagonal, Variant::AntiKnight].iter() {
+            let mut board = Board::generate(9, 30, &mut rng);
+            board.variant = variant;
+            for i in 0..40 {
+                let (x, y) = (i * 7 % 9, i * 4 % 9);
+                board.set_mut(x, y, Cell::Variable((i % 9 + 1) as u8));
+                assert_eq!(board.is_valid(), board.conflicts().is_empty());
+            }
+        }
+        let hard =
+            "8..........36......7..9.2...5...7.......457.....1...3...1....68..85...1..9....4..";
+        assert!(board_from_str(hard).solve().unwrap().is_valid());
+    }
+
+    #[test]
+    fn test_conflicting_givens_fail_at_once() {
+        // Two 9s in the bottom row, far from where the search starts.
+        let board = board_from_str(PUZZLE_9X9).set(0, 8, Cell::Constant(9));
+        assert_eq!(board.solve_with_stats(), (None, SolveStats::default()));
+        assert_eq!(board.solve(), None);
+        assert_eq!(board.count_solutions(2), 0);
+        assert_eq!(Board::solve_step(&mut SolverState::new(&board)), StepResult::Failed);
+    }
+
+    #[test]
+    fn test_givens() {
+        let board = Board::new(4)
+            .set(3, 0, Cell::Constant(2))
+            .set(1, 2, Cell::Variable(4))
+            .set(0, 1, Cell::Constant(1))
+            .set(2, 3, Cell::Constant(3));
+        assert_eq!(board.givens(), vec![(3, 0, 2), (0, 1, 1), (2, 3, 3)]);
+        assert!(Board::new(9).givens().is_empty());
+        assert_eq!(board_from_str(PUZZLE_9X9).givens().len(), 30);
+    }
+
+    #[test]
+    #[cfg(all(feature = "threads", not(target_arch = "wasm32")))]
+    fn test_solve_parallel() {
+        let board = board_from_str(PUZZLE_9X9);
+        let solution = board.solve_parallel().unwrap();
+        assert!(solution.is_solved());
+        assert!(solution.squares.iter().all(|c| *c != Cell::Empty));
+        for &(x, y, v) in board.givens().iter() {
+            assert_eq!(solution.get(x, y), Cell::Constant(v));
+        }
+
+        let board = Board::try_new(16).unwrap();
+        assert!(board.solve_parallel().unwrap().is_solved());
+
+        let board = board_from_str(PUZZLE_9X9).set(2, 0, Cell::Constant(5));
+        assert_eq!(board.solve_parallel(), None);
+    }
+
+    #[test]
+    fn test_placed_counts() {
+        let board = Board::new(4)
+            .set(0, 0, Cell::Constant(1))
+            .set(1, 2, Cell::Constant(1))
+            .set(3, 1, Cell::Variable(1))
+            .set(2, 3, Cell::Variable(3))
+            .toggle_note(0, 3, 2);
+        assert_eq!(board.placed_counts(), vec![0, 3, 0, 1, 0]);
+        assert_eq!(Board::new(9).placed_counts(), vec![0; 10]);
+        let solution = board_from_str(PUZZLE_9X9).solve().unwrap();
+        assert_eq!(solution.placed_counts(), [vec![0], vec![9; 9]].concat());
+    }
+
+    #[test]
+    fn test_fill_notes() {
+        let board = Board::new(4)
+            .set(0, 0, Cell::Constant(1))
+            .set(1, 1, Cell::Variable(2))
+            .set(3, 3, Cell::Constant(4))
+            .toggle_note(2, 2, 1)
+            .fill_notes();
+        for y in 0..4 {
+            for x in 0..4 {
+                assert_eq!(board.get_notes(x, y), board.candidates(x, y));
+            }
+        }
+        assert_eq!(board.get_notes(0, 0), Vec::<u8>::new());
+        assert_eq!(board.get_notes(1, 0), vec![3, 4]);
+        assert_eq!(board.get_notes(2, 2), vec![1, 2, 3]);
+
+        // Entering a value takes it out of the notes of its peers only.
+        let board = board.set(2, 0, Cell::Variable(3)).prune_notes(2, 0);
+        assert_eq!(board.get_notes(1, 0), vec![4]);
+        assert_eq!(board.get_notes(2, 2), vec![1, 2]);
+        assert_eq!(board.get_notes(3, 1), vec![1]);
+        assert_eq!(board.get_notes(0, 2), vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn test_enter_leaves_givens_alone() {
+        let board = board_from_str(PUZZLE_9X9);
+        assert_eq!(board.get(0, 0), Cell::Constant(5));
+        assert_eq!(board.enter(0, 0, Some(1)), board);
+        assert_eq!(board.enter(0, 0, None), board);
+
+        let board = board.enter(2, 0, Some(4));
+        assert_eq!(board.get(2, 0), Cell::Variable(4));
+        assert_eq!(board.enter(2, 0, None).get(2, 0), Cell::Empty);
+    }
+
+    #[test]
+    fn test_is_valid_value() {
+        for &n in [4, 9, 16].iter() {
+            let board = Board::new(n);
+            assert!(!board.is_valid_value(0));
+            assert!(board.is_valid_value(1));
+            assert!(board.is_valid_value(n as u8));
+            assert!(!board.is_valid_value(n as u8 + 1));
+        }
+        assert!(!Board::new(16).is_valid_value(u8::MAX));
+    }
+
+    #[test]
+    fn test_units() {
+        use Cell::{Constant as C, Empty as E, Variable as V};
+        let board = Board::with_boxes(6, 3, 2)
+            .set(0, 0, C(1))
+            .set(2, 1, V(4))
+            .set(4, 1, C(6))
+            .set(2, 5, C(3));
+        assert_eq!(board.row(1), vec![E, E, V(4), E, C(6), E]);
+        assert_eq!(board.row(3), vec![E; 6]);
+        assert_eq!(board.col(2), vec![E, V(4), E, E, E, C(3)]);
+        assert_eq!(board.box_cells(1, 1), vec![C(1), E, E, E, E, V(4)]);
+        assert_eq!(board.box_cells(5, 0), vec![E, E, E, E, C(6), E]);
+        assert_eq!(board.box_cells(2, 5), board.box_cells(0, 4));
+        assert_eq!(board.box_cells(2, 5)[5], C(3));
+    }
+
+    #[test]
+    fn test_killer_cage() {
+        let cage = Cage {
+            cells: vec![(0, 0), (1, 0)],
+            sum: 3,
+        };
+        let mut board = Board::new(4);
+        board.cages.push(cage.clone());
+        let solution = board.solve().unwrap();
+        assert!(solution.is_solved());
+        let mut values = vec![solution.get(0, 0), solution.get(1, 0)];
+        values.sort_by_key(|cell| cell.value());
+        assert_eq!(values, vec![Cell::Variable(1), Cell::Variable(2)]);
+        assert_eq!(board.candidates(0, 0), vec![1, 2]);
+        assert!(board.is_peer((0, 0), (1, 0)));
+
+        // Going over the sum, or leaving too little for the rest of the cage,
+        // breaks it. So does filling it with too little.
+        assert!(!board.set(0, 0, Cell::Constant(4)).is_valid());
+        assert!(!board.set(0, 0, Cell::Constant(3)).is_valid());
+        assert!(board.set(0, 0, Cell::Constant(2)).is_valid());
+        let short = board
+            .set(0, 0, Cell::Constant(1))
+            .set(1, 0, Cell::Constant(1));
+        assert!(!short.is_valid());
+        assert_eq!(short.conflicts(), vec![(0, 0), (1, 0)]);
+
+        // No two different values add up to 2.
+        let mut board = Board::new(4);
+        board.cages.push(Cage { sum: 2, ..cage });
+        assert_eq!(board.solve(), None);
+    }
+
+    #[test]
+    fn test_cages_json() {
+        let mut board = Board::new(4).set(0, 0, Cell::Constant(1));
+        board.cages.push(Cage {
+            cells: vec![(0, 0), (1, 0)],
+            sum: 3,
+        });
+        board.cages.push(Cage {
+            cells: vec![(2, 3), (3, 3), (3, 2)],
+            sum: 7,
+        });
+        let json = board.to_json();
+        assert!(json.ends_with(
+            r#"],"cages":[{"cells":[[0,0],[1,0]],"sum":3},{"cells":[[2,3],[3,3],[3,2]],"sum":7}]}"#
+        ));
+        assert_eq!(Board::from_json(&json), Ok(board));
+        assert!(!Board::new(4).to_json().contains("cages"));
+
+        // Cells off the board, or sums that aren't whole numbers, are rejected.
+        let cells = vec![r#"{"kind":"empty"}"#; 16].join(",");
+        for cages in &[
+            r#"[{"cells":[[0,4]],"sum":1}]"#,
+            r#"[{"cells":[[0,0]],"sum":1.5}]"#,
+            r#"[{"cells":[0,0],"sum":1}]"#,
+            r#"{"cells":[[0,0]],"sum":1}"#,
+        ] {
+            let json = format!(r#"{{"n":4,"cells":[{}],"cages":{}}}"#, cells, cages);
+            assert!(Board::from_json(&json).is_err());
+        }
+    }
+
+    #[test]
+    fn test_iter_cells() {
+        let board = Board::new(4).set(1, 2, Cell::Constant(3));
+        let cells: Vec<(usize, usize, Cell)> = board.iter_cells().collect();
+        assert_eq!(cells.len(), 16);
+        let coordinates: Vec<(usize, usize)> = cells.iter().map(|&(x, y, _)| (x, y)).collect();
+        let expected: Vec<(usize, usize)> =
+            (0..4).flat_map(|y| (0..4).map(move |x| (x, y))).collect();
+        assert_eq!(coordinates, expected);
+        assert_eq!(cells[0..2], [(0, 0, Cell::Empty), (1, 0, Cell::Empty)]);
+        assert_eq!(cells[9], (1, 2, Cell::Constant(3)));
+        assert_eq!(cells[15], (3, 3, Cell::Empty));
+    }
+
+    #[test]
+    fn test_has_unique_solution() {
+        assert!(board_from_str(PUZZLE_9X9).has_unique_solution());
+        // An empty board, or one with a single given, has a great many.
+        assert!(!Board::new(4).has_unique_solution());
+        assert!(!Board::new(9)
+            .set(4, 4, Cell::Constant(5))
+            .has_unique_solution());
+        // Two 5s in the first row.
+        let contradictory = board_from_str(PUZZLE_9X9).set(2, 0, Cell::Constant(5));
+        assert_eq!(contradictory.count_solutions(2), 0);
+        assert!(!contradictory.has_unique_solution());
+    }
+
+    #[test]
+    fn test_apply_hidden_singles() {
+        // The 1s rule out all of the top left box but its corner, which
+        // could still hold any value as far as its row and column go.
+        let board = Board::new(9)
+            .set(5, 1, Cell::Constant(1))
+            .set(7, 2, Cell::Constant(1))
+            .set(1, 5, Cell::Constant(1))
+            .set(2, 7, Cell::Constant(1));
+        assert_eq!(board.candidates(0, 0).len(), 9);
+        let filled = board.apply_hidden_singles().unwrap();
+        assert_eq!(filled.get(0, 0), Cell::Variable(1));
+        assert!(filled.is_valid());
+        assert_eq!(board.solve_steps()[0].technique, Technique::HiddenSingle);
+
+        // Nothing is hidden on an empty board.
+        assert_eq!(Board::new(4).apply_hidden_singles(), None);
+    }
+
+    #[test]
+    fn test_colors() {
+        let board = board_from_str(PUZZLE_9X9)
+            .set_color(0, 0, Some(2))
+            .set_color(2, 0, Some(5));
+        // Both a given and an empty cell can be painted.
+        assert_eq!(board.get_color(0, 0), Some(2));
+        assert_eq!(board.get_color(2, 0), Some(5));
+        assert_eq!(board.get_color(1, 0), None);
+        assert_eq!(board.set_color(0, 0, None).get_color(0, 0), None);
+
+        // Filling a painted cell keeps its color, and so does turning the
+        // board and saving it.
+        let filled = board.set(2, 0, Cell::Variable(4));
+        assert_eq!(filled.get_color(2, 0), Some(5));
+        assert_eq!(board.rotate_90().get_color(8, 2), Some(5));
+        assert_eq!(Board::from_json(&filled.to_json()).unwrap(), filled);
+        assert!(Board::from_json(
+            &Board::new(4)
+                .to_json()
+                .replace("null}", r#"null,"color":-1}"#)
+        )
+        .is_err());
+
+        // Clearing the board takes the colors away, restarting it doesn't.
+        assert_eq!(board.clear().get_color(0, 0), None);
+        assert_eq!(board.clear_variables().get_color(0, 0), Some(2));
+        assert_eq!(board.solve().unwrap().get_color(0, 0), Some(2));
+    }
+
+    #[test]
+    fn test_solve_bounded() {
+        let hard =
+            "8..........36......7..9.2...5...7.......457.....1...3...1....68..85...1..9....4..";
+        let board = board_from_str(hard);
+        assert_eq!(board.solve_bounded(100), Err(BudgetExceeded));
+        let nodes = board.solve_with_stats().1.nodes;
+        assert_eq!(board.solve_bounded(nodes), Ok(board.solve()));
+        assert_eq!(board.difficulty_bounded(100), Err(BudgetExceeded));
+        assert_eq!(board.difficulty_bounded(nodes), Ok(board.difficulty()));
+        assert_eq!(board.unique_solution_bounded(100), Err(BudgetExceeded));
+        assert_eq!(
+            board.unique_solution_bounded(usize::MAX),
+            Ok(board.unique_solution())
+        );
+        let solution = board.solve().unwrap();
+        assert_eq!(board.hint_from(&solution, None), board.hint());
+        assert_eq!(board.steps_from(&solution), board.solve_steps());
+        assert_eq!(
+            board.incorrect_cells_from(Some(&solution)),
+            board.incorrect_cells()
+        );
+
+        // Singles solve this one before the search visits a single cell.
+        let easy = board_from_str(PUZZLE_9X9);
+        assert_eq!(easy.solve_bounded(0), Ok(easy.solve()));
+        let conflicting = easy.set(2, 0, Cell::Constant(5));
+        assert_eq!(conflicting.solve_bounded(0), Ok(None));
+    }
+
+    // A plain recursive backtracking solver, filling the first empty cell
+    // with each value in turn, to check the search against.
+    fn solve_recursively(board: &mut Board) -> bool {
+        let i = match board.squares.iter().position(|&cell| cell == Cell::Empty) {
+            Some(i) => i,
+            None => return board.is_valid(),
+        };
+        let (x, y) = (i % board.n, i / board.n);
+        for v in 1..=board.n as u8 {
+            board.set_mut(x, y, Cell::Variable(v));
+            if board.within_constraints(x, y) && solve_recursively(board) {
+                return true;
+            }
+        }
+        board.set_mut(x, y, Cell::Empty);
+        false
+    }
+
+    #[test]
+    fn test_search_matches_recursive_solver() {
+        let puzzles = [
+            PUZZLE_9X9,
+            "1.4...3...5..4.9..2...98......16.......8..7.4.27....36...9....1...5.....36......2",
+            ".189..654...4....7..721...9......2......6..459...8....5...2..766..84..9...3..5...",
+            ".6.......4...7..6...7..9258...2.......9..8..7..15....97....68..3...5.......3...15",
+            &format!("{:.<81}", "1...............2"),
         ];
-        let board = Board::from(&squares);
-        let solution = board.solve();
-        assert_eq!(solution, None);
+        for puzzle in puzzles.iter() {
+            let board = board_from_str(puzzle);
+            let mut expected = board.clone();
+            assert!(solve_recursively(&mut expected));
+            // The naive solver's pick among several solutions is the first
+            // in reading order, which the search doesn't promise.
+            if board.has_unique_solution() {
+                assert_eq!(board.solve(), Some(expected));
+            } else {
+                assert!(board.solve().unwrap().is_solved());
+            }
+        }
+
+        let mut board = board_from_str(PUZZLE_9X9).set(2, 0, Cell::Constant(5));
+        assert_eq!(board.solve(), None);
+        assert!(!solve_recursively(&mut board));
     }
 }
@@ -0,0 +1,11 @@
+// Solves the boards that `solve_in_workers` in src/lib.rs sends, one board per
+// message, and answers with the solution or null.
+importScripts('/pkg/package.js');
+
+const ready = wasm_bindgen('/pkg/package_bg.wasm');
+
+onmessage = function (event) {
+    ready.then(function () {
+        postMessage(wasm_bindgen.solve_line(event.data) || null);
+    });
+};